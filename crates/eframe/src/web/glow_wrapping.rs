@@ -84,6 +84,7 @@ impl WrappedGlowPainter {
         for &id in &textures_delta.free {
             self.free_texture(id);
         }
+        self.painter.flush_textures_to_destroy();
 
         Ok(())
     }
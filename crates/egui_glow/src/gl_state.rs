@@ -0,0 +1,237 @@
+#![allow(unsafe_code)]
+
+use glow::HasContext as _;
+
+/// The OpenGL state that [`crate::Painter::paint_primitives`] changes,
+/// saved up front so it can be put back afterwards.
+///
+/// See [`crate::Painter::set_preserve_gl_state`].
+pub(crate) struct GlState {
+    viewport: [i32; 4],
+    scissor: ScissorState,
+    blend: bool,
+    blend_equation_rgb: u32,
+    blend_equation_alpha: u32,
+    blend_func: [u32; 4],
+    color_mask: [bool; 4],
+    cull_face: bool,
+    cull_face_mode: u32,
+    front_face: u32,
+    depth_test: bool,
+    stencil_test: bool,
+    framebuffer_srgb: bool,
+    active_texture: u32,
+
+    /// Raw GL names. Zero means nothing was bound.
+    program: u32,
+    texture_2d: u32,
+    /// `None` if vertex array objects are not supported.
+    vertex_array: Option<u32>,
+    array_buffer: u32,
+    element_array_buffer: u32,
+}
+
+impl GlState {
+    /// `vertex_arrays` is whether vertex array objects are supported, so that we can query the bound one.
+    pub(crate) unsafe fn save(gl: &glow::Context, vertex_arrays: bool) -> Self {
+        crate::profile_function!();
+
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+
+        // WebGL reports the color mask as an array of booleans, which `glow` can't read,
+        // so this stays at the default there.
+        let mut color_mask = [1; 4];
+        gl.get_parameter_i32_slice(glow::COLOR_WRITEMASK, &mut color_mask);
+
+        // We paint with texture unit 0, so that's the texture binding we need to save:
+        let active_texture = gl.get_parameter_i32(glow::ACTIVE_TEXTURE) as u32;
+        gl.active_texture(glow::TEXTURE0);
+        let texture_2d = gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32;
+
+        Self {
+            viewport,
+            scissor: ScissorState::save(gl),
+            blend: gl.is_enabled(glow::BLEND),
+            blend_equation_rgb: gl.get_parameter_i32(glow::BLEND_EQUATION_RGB) as u32,
+            blend_equation_alpha: gl.get_parameter_i32(glow::BLEND_EQUATION_ALPHA) as u32,
+            blend_func: [
+                gl.get_parameter_i32(glow::BLEND_SRC_RGB) as u32,
+                gl.get_parameter_i32(glow::BLEND_DST_RGB) as u32,
+                gl.get_parameter_i32(glow::BLEND_SRC_ALPHA) as u32,
+                gl.get_parameter_i32(glow::BLEND_DST_ALPHA) as u32,
+            ],
+            color_mask: color_mask.map(|c| c != 0),
+            cull_face: gl.is_enabled(glow::CULL_FACE),
+            cull_face_mode: gl.get_parameter_i32(glow::CULL_FACE_MODE) as u32,
+            front_face: gl.get_parameter_i32(glow::FRONT_FACE) as u32,
+            depth_test: gl.is_enabled(glow::DEPTH_TEST),
+            stencil_test: gl.is_enabled(glow::STENCIL_TEST),
+            framebuffer_srgb: !cfg!(target_arch = "wasm32")
+                && gl.is_enabled(glow::FRAMEBUFFER_SRGB),
+            active_texture,
+            program: gl.get_parameter_i32(glow::CURRENT_PROGRAM) as u32,
+            texture_2d,
+            vertex_array: if vertex_arrays {
+                Some(gl.get_parameter_i32(glow::VERTEX_ARRAY_BINDING) as u32)
+            } else {
+                None
+            },
+            array_buffer: gl.get_parameter_i32(glow::ARRAY_BUFFER_BINDING) as u32,
+            element_array_buffer: gl.get_parameter_i32(glow::ELEMENT_ARRAY_BUFFER_BINDING) as u32,
+        }
+    }
+
+    /// A human-readable dump of the current state, for debugging. Leaves the state as it was.
+    pub(crate) unsafe fn describe(gl: &glow::Context, vertex_arrays: bool) -> String {
+        let state = Self::save(gl, vertex_arrays);
+        gl.active_texture(state.active_texture); // `save` switched to unit 0
+        let framebuffer = gl.get_parameter_i32(glow::FRAMEBUFFER_BINDING);
+        format!("{:#?}\nframebuffer: {}", state, framebuffer)
+    }
+
+    pub(crate) unsafe fn restore(&self, gl: &glow::Context) {
+        crate::profile_function!();
+
+        gl.viewport(
+            self.viewport[0],
+            self.viewport[1],
+            self.viewport[2],
+            self.viewport[3],
+        );
+        self.scissor.restore(gl);
+        set_enabled(gl, glow::BLEND, self.blend);
+        gl.blend_equation_separate(self.blend_equation_rgb, self.blend_equation_alpha);
+        gl.blend_func_separate(
+            self.blend_func[0],
+            self.blend_func[1],
+            self.blend_func[2],
+            self.blend_func[3],
+        );
+        let [r, g, b, a] = self.color_mask;
+        gl.color_mask(r, g, b, a);
+        set_enabled(gl, glow::CULL_FACE, self.cull_face);
+        // We don't change these ourselves, but callbacks might:
+        gl.cull_face(self.cull_face_mode);
+        gl.front_face(self.front_face);
+        set_enabled(gl, glow::DEPTH_TEST, self.depth_test);
+        set_enabled(gl, glow::STENCIL_TEST, self.stencil_test);
+        if !cfg!(target_arch = "wasm32") {
+            set_enabled(gl, glow::FRAMEBUFFER_SRGB, self.framebuffer_srgb);
+        }
+
+        // `glow` has no way to refer to an object by its raw name, except for native textures.
+        // So other objects can only be restored if nothing was bound.
+        if self.program == 0 {
+            gl.use_program(None);
+        }
+        // The element array buffer binding is part of the vertex array state, so restore that first:
+        if self.vertex_array == Some(0) {
+            gl.bind_vertex_array(None);
+        }
+        if self.array_buffer == 0 {
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+        if self.element_array_buffer == 0 {
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+        }
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, texture_from_gl_name(self.texture_2d));
+        gl.active_texture(self.active_texture);
+    }
+}
+
+impl std::fmt::Debug for GlState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep the arrays on one line each, even with `{:#?}`:
+        f.debug_struct("GlState")
+            .field("viewport", &format_args!("{:?}", self.viewport))
+            .field("scissor_test", &self.scissor.enabled)
+            .field(
+                "scissor_box",
+                &format_args!("{:?}", self.scissor.scissor_box),
+            )
+            .field("blend", &self.blend)
+            .field(
+                "blend_equation",
+                &GlEnums(&[self.blend_equation_rgb, self.blend_equation_alpha]),
+            )
+            .field("blend_func", &GlEnums(&self.blend_func))
+            .field("color_mask", &format_args!("{:?}", self.color_mask))
+            .field("cull_face", &self.cull_face)
+            .field("cull_face_mode", &GlEnums(&[self.cull_face_mode]))
+            .field("front_face", &GlEnums(&[self.front_face]))
+            .field("depth_test", &self.depth_test)
+            .field("stencil_test", &self.stencil_test)
+            .field("framebuffer_srgb", &self.framebuffer_srgb)
+            .field("active_texture", &GlEnums(&[self.active_texture]))
+            .field("program", &self.program)
+            .field("texture_2d", &self.texture_2d)
+            .field("vertex_array", &format_args!("{:?}", self.vertex_array))
+            .field("array_buffer", &self.array_buffer)
+            .field("element_array_buffer", &self.element_array_buffer)
+            .finish()
+    }
+}
+
+/// Formats GL enums in hex, which is how they are listed in the headers.
+struct GlEnums<'a>(&'a [u32]);
+
+impl std::fmt::Debug for GlEnums<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, value) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "0x{:04X}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the scissor test is enabled, and the scissor box.
+///
+/// See [`crate::Painter::set_preserve_scissor`].
+pub(crate) struct ScissorState {
+    enabled: bool,
+    scissor_box: [i32; 4],
+}
+
+impl ScissorState {
+    pub(crate) unsafe fn save(gl: &glow::Context) -> Self {
+        let mut scissor_box = [0; 4];
+        gl.get_parameter_i32_slice(glow::SCISSOR_BOX, &mut scissor_box);
+        Self {
+            enabled: gl.is_enabled(glow::SCISSOR_TEST),
+            scissor_box,
+        }
+    }
+
+    pub(crate) unsafe fn restore(&self, gl: &glow::Context) {
+        set_enabled(gl, glow::SCISSOR_TEST, self.enabled);
+        gl.scissor(
+            self.scissor_box[0],
+            self.scissor_box[1],
+            self.scissor_box[2],
+            self.scissor_box[3],
+        );
+    }
+}
+
+unsafe fn set_enabled(gl: &glow::Context, parameter: u32, enabled: bool) {
+    if enabled {
+        gl.enable(parameter);
+    } else {
+        gl.disable(parameter);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn texture_from_gl_name(name: u32) -> Option<glow::Texture> {
+    (name != 0).then(|| glow::Context::create_texture_from_gl_name(name))
+}
+
+#[cfg(target_arch = "wasm32")]
+unsafe fn texture_from_gl_name(_name: u32) -> Option<glow::Texture> {
+    None // WebGL doesn't report bindings as numbers
+}
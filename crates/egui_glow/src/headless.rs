@@ -0,0 +1,74 @@
+//! Painting without a window, e.g. for tests that compare a layout with a golden image.
+
+use std::sync::Arc;
+
+use crate::{Painter, PainterError};
+
+/// A [`Painter`] that paints into a framebuffer of its own, and reads back the result.
+///
+/// You provide the context, and make it current. It can be headless (e.g. a surfaceless EGL context)
+/// or belong to a hidden window; load it with e.g. [`glow::Context::from_loader_function`].
+///
+/// ```no_run
+/// # let gl: std::sync::Arc<glow::Context> = todo!();
+/// # let ctx = egui::Context::default();
+/// let mut painter = egui_glow::HeadlessPainter::new(gl, [320, 240]).unwrap();
+/// let output = ctx.run(Default::default(), |ctx| {
+///     egui::CentralPanel::default().show(ctx, |ui| ui.label("Hello"));
+/// });
+/// let primitives = ctx.tessellate(output.shapes);
+/// let image = painter.paint(1.0, &primitives, &output.textures_delta).unwrap();
+/// assert_eq!(image.size, [320, 240]);
+/// painter.destroy();
+/// ```
+pub struct HeadlessPainter {
+    painter: Painter,
+    size_px: [u32; 2],
+}
+
+impl HeadlessPainter {
+    /// Paint images of `size_px` pixels with `gl`.
+    pub fn new(gl: Arc<glow::Context>, size_px: [u32; 2]) -> Result<Self, PainterError> {
+        Ok(Self {
+            painter: Painter::new(gl, None, "")?,
+            size_px,
+        })
+    }
+
+    /// The painter, e.g. to change its settings.
+    pub fn painter(&mut self) -> &mut Painter {
+        &mut self.painter
+    }
+
+    /// Paint the primitives onto a transparent background, after applying `textures_delta`,
+    /// and read back the result, with premultiplied alpha like [`egui::Color32`].
+    ///
+    /// Keep passing the [`egui::TexturesDelta`] of every frame, as egui only sends textures when they change.
+    /// This waits for the GPU to finish painting.
+    ///
+    /// # Errors
+    /// Like [`Painter::render_to_texture`], e.g. if a texture in `textures_delta` can't be uploaded.
+    pub fn paint(
+        &mut self,
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+    ) -> Result<egui::ColorImage, PainterError> {
+        crate::profile_function!();
+        self.painter.render_to_texture(
+            self.size_px,
+            pixels_per_point,
+            clipped_primitives,
+            textures_delta,
+        )?;
+        Ok(self
+            .painter
+            .read_render_target()
+            .expect("render_to_texture creates the render target"))
+    }
+
+    /// Delete the OpenGL objects, see [`Painter::destroy`].
+    pub fn destroy(&mut self) {
+        self.painter.destroy();
+    }
+}
@@ -13,18 +13,37 @@
 
 pub mod painter;
 pub use glow;
-pub use painter::{CallbackFn, Painter};
+pub use painter::{
+    BufferUsage, CallbackFn, ClipRounding, InstancedMeshId, PaintStats, Painter, PainterError,
+    ScreenshotToken, TextureOptions, TextureSender, TextureTile, TextureWrapMode, TiledTexture,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use program_cache::ProgramCache;
+pub use shader_version::ShaderVersion;
+pub use texture_registry::TextureRegistry;
+#[cfg(feature = "headless")]
+mod headless;
+#[cfg(feature = "headless")]
+pub use headless::HeadlessPainter;
+mod gl_state;
+mod log;
 mod misc_util;
+mod persistent_buffer;
 mod post_process;
+#[cfg(not(target_arch = "wasm32"))]
+mod program_cache;
+mod screenshot;
 mod shader_version;
-mod vao;
+mod stream_buffer;
+mod texture_registry;
+pub mod vao;
 
 #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
 pub mod winit;
 #[cfg(all(not(target_arch = "wasm32"), feature = "winit"))]
 pub use winit::*;
 
-/// Check for OpenGL error and report it using `tracing::error`.
+/// Check for OpenGL error and report it using `tracing::error` (or `log::error`, with the `log` feature).
 ///
 /// Only active in debug builds!
 ///
@@ -48,7 +67,7 @@ macro_rules! check_for_gl_error {
     }};
 }
 
-/// Check for OpenGL error and report it using `tracing::error`.
+/// Check for OpenGL error and report it using `tracing::error` (or `log::error`, with the `log` feature).
 ///
 /// WARNING: slow! Only use during setup!
 ///
@@ -74,22 +93,10 @@ pub fn check_for_gl_error_impl(gl: &glow::Context, file: &str, line: u32, contex
     #[allow(unsafe_code)]
     let error_code = unsafe { gl.get_error() };
     if error_code != glow::NO_ERROR {
-        let error_str = match error_code {
-            glow::INVALID_ENUM => "GL_INVALID_ENUM",
-            glow::INVALID_VALUE => "GL_INVALID_VALUE",
-            glow::INVALID_OPERATION => "GL_INVALID_OPERATION",
-            glow::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
-            glow::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
-            glow::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
-            glow::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
-            glow::CONTEXT_LOST => "GL_CONTEXT_LOST",
-            0x8031 => "GL_TABLE_TOO_LARGE1",
-            0x9242 => "CONTEXT_LOST_WEBGL",
-            _ => "<unknown>",
-        };
+        let error_str = gl_error_name(error_code);
 
         if context.is_empty() {
-            tracing::error!(
+            crate::log::error!(
                 "GL error, at {}:{}: {} (0x{:X}). Please file a bug at https://github.com/emilk/egui/issues",
                 file,
                 line,
@@ -97,7 +104,7 @@ pub fn check_for_gl_error_impl(gl: &glow::Context, file: &str, line: u32, contex
                 error_code,
             );
         } else {
-            tracing::error!(
+            crate::log::error!(
                 "GL error, at {}:{} ({}): {} (0x{:X}). Please file a bug at https://github.com/emilk/egui/issues",
                 file,
                 line,
@@ -109,6 +116,44 @@ pub fn check_for_gl_error_impl(gl: &glow::Context, file: &str, line: u32, contex
     }
 }
 
+fn gl_error_name(error_code: u32) -> &'static str {
+    match error_code {
+        glow::INVALID_ENUM => "GL_INVALID_ENUM",
+        glow::INVALID_VALUE => "GL_INVALID_VALUE",
+        glow::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        glow::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        glow::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        glow::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        glow::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        glow::CONTEXT_LOST => "GL_CONTEXT_LOST",
+        0x8031 => "GL_TABLE_TOO_LARGE1",
+        0x9242 => "CONTEXT_LOST_WEBGL",
+        _ => "<unknown>",
+    }
+}
+
+/// The OpenGL errors that were pending, see [`Painter::take_gl_error`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlError {
+    /// The error codes (e.g. `glow::INVALID_OPERATION`), in the order `glGetError` returned them.
+    pub codes: Vec<u32>,
+}
+
+impl std::fmt::Display for GlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("GL error: ")?;
+        for (i, &code) in self.codes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} (0x{:X})", gl_error_name(code), code)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GlError {}
+
 // ---------------------------------------------------------------------------
 
 /// Profiling macro for feature "puffin"
@@ -130,3 +175,14 @@ macro_rules! profile_scope {
     };
 }
 pub(crate) use profile_scope;
+
+#[test]
+fn test_gl_error_display() {
+    let error = GlError {
+        codes: vec![glow::INVALID_ENUM, glow::OUT_OF_MEMORY],
+    };
+    assert_eq!(
+        error.to_string(),
+        "GL error: GL_INVALID_ENUM (0x500), GL_OUT_OF_MEMORY (0x505)"
+    );
+}
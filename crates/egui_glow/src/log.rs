@@ -0,0 +1,21 @@
+//! The logging macros of the crate: those of [`tracing`](https://docs.rs/tracing) by default,
+//! or those of [`log`](https://docs.rs/log) with the `log` feature.
+//!
+//! With neither feature, nothing is logged.
+
+#[cfg(feature = "log")]
+pub(crate) use ::log::{debug, error, warn};
+
+#[cfg(all(feature = "tracing", not(feature = "log")))]
+pub(crate) use ::tracing::{debug, error, warn};
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! discard {
+    ($($arg:tt)*) => {{
+        // Still type-check the arguments, and count them as used:
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+pub(crate) use {discard as debug, discard as error, discard as warn};
@@ -2,6 +2,8 @@
 
 use glow::HasContext as _;
 
+/// On failure, the error is the driver log followed by the numbered source,
+/// since the log refers to lines of the complete source, including any header and defines.
 pub(crate) unsafe fn compile_shader(
     gl: &glow::Context,
     shader_type: u32,
@@ -16,25 +18,70 @@ pub(crate) unsafe fn compile_shader(
     if gl.get_shader_compile_status(shader) {
         Ok(shader)
     } else {
-        Err(gl.get_shader_info_log(shader))
+        let log = gl.get_shader_info_log(shader);
+        gl.delete_shader(shader);
+        Err(format!(
+            "{}\nSource:\n{}",
+            log.trim_end(),
+            numbered_source(source)
+        ))
     }
 }
 
-pub(crate) unsafe fn link_program<'a, T: IntoIterator<Item = &'a glow::Shader>>(
+/// `shaders` are the compiled shaders together with their source,
+/// which is listed after the driver log on failure.
+pub(crate) unsafe fn link_program(
     gl: &glow::Context,
-    shaders: T,
+    shaders: &[(glow::Shader, &str)],
+) -> Result<glow::Program, String> {
+    link_program_with_attributes(gl, shaders, &[], |_| ())
+}
+
+/// Like [`link_program`], but binds each named vertex attribute to its location first,
+/// and calls `before_link` with the program just before linking it, e.g. to set program parameters.
+pub(crate) unsafe fn link_program_with_attributes(
+    gl: &glow::Context,
+    shaders: &[(glow::Shader, &str)],
+    attribute_locations: &[(u32, &str)],
+    before_link: impl FnOnce(glow::Program),
 ) -> Result<glow::Program, String> {
     let program = gl.create_program()?;
 
-    for shader in shaders {
+    for (shader, _) in shaders {
         gl.attach_shader(program, *shader);
     }
+    for (location, name) in attribute_locations {
+        gl.bind_attrib_location(program, *location, name);
+    }
+    before_link(program);
 
     gl.link_program(program);
 
     if gl.get_program_link_status(program) {
         Ok(program)
     } else {
-        Err(gl.get_program_info_log(program))
+        let mut error = gl.get_program_info_log(program).trim_end().to_owned();
+        gl.delete_program(program);
+        for (i, (_, source)) in shaders.iter().enumerate() {
+            error += &format!("\nSource of shader {}:\n{}", i + 1, numbered_source(source));
+        }
+        Err(error)
     }
 }
+
+/// Prefix each line with its number, starting at 1 like the line numbers in shader logs.
+fn numbered_source(source: &str) -> String {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| format!("{:4}: {}\n", i + 1, line))
+        .collect()
+}
+
+#[test]
+fn test_numbered_source() {
+    assert_eq!(
+        numbered_source("#version 140\n\nvoid main() {}\n"),
+        "   1: #version 140\n   2: \n   3: void main() {}\n"
+    );
+}
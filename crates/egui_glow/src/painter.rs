@@ -1,24 +1,39 @@
 #![allow(unsafe_code)]
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
 
 use egui::{
     emath::Rect,
+    epaint::ImageDelta,
     epaint::{Color32, Mesh, PaintCallbackInfo, Primitive, Vertex},
+    mutex::Mutex,
 };
 use glow::HasContext as _;
 use memoffset::offset_of;
 
 use crate::check_for_gl_error;
-use crate::misc_util::{compile_shader, link_program};
+use crate::gl_state::{GlState, ScissorState};
+use crate::misc_util::{compile_shader, link_program_with_attributes};
+use crate::persistent_buffer::PersistentMeshBuffer;
 use crate::post_process::PostProcess;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::program_cache::ProgramCache;
+use crate::screenshot::PendingScreenshot;
 use crate::shader_version::ShaderVersion;
+use crate::stream_buffer::StreamBuffer;
+use crate::texture_registry::TextureRegistry;
 use crate::vao;
 
 pub use glow::Context;
 
 const VERT_SRC: &str = include_str!("shader/vertex.glsl");
 const FRAG_SRC: &str = include_str!("shader/fragment.glsl");
+const FRAG_MINIMAL_SRC: &str = include_str!("shader/fragment_minimal.glsl");
 
 pub type TextureFilter = egui::TextureFilter;
 
@@ -35,607 +50,4242 @@ impl TextureFilterExt for TextureFilter {
     }
 }
 
-/// An OpenGL painter using [`glow`].
-///
-/// This is responsible for painting egui and managing egui textures.
-/// You can access the underlying [`glow::Context`] with [`Self::gl`].
-///
-/// This struct must be destroyed with [`Painter::destroy`] before dropping, to ensure OpenGL
-/// objects have been properly deleted and are not leaked.
-pub struct Painter {
-    gl: Arc<glow::Context>,
-
-    max_texture_side: usize,
+/// How clip rectangles are rounded to whole pixels, see [`Painter::set_clip_rounding`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClipRounding {
+    /// Round each edge to the nearest pixel.
+    ///
+    /// This is the default.
+    Round,
 
-    program: glow::Program,
-    u_screen_size: glow::UniformLocation,
-    u_sampler: glow::UniformLocation,
-    is_webgl_1: bool,
-    is_embedded: bool,
-    vao: crate::vao::VertexArrayObject,
-    srgb_support: bool,
-    post_process: Option<PostProcess>,
-    vbo: glow::Buffer,
-    element_array_buffer: glow::Buffer,
+    /// Round the min edges down and the max edges up, so that a clip rectangle covers every pixel it touches.
+    ///
+    /// Abutting clip rectangles then never leave a seam between them, at the cost of overlapping by up to a pixel.
+    Outward,
+}
 
-    textures: HashMap<egui::TextureId, glow::Texture>,
+impl Default for ClipRounding {
+    fn default() -> Self {
+        Self::Round
+    }
+}
 
-    next_native_tex_id: u64,
+/// How texture coordinates outside of `[0, 1]` are sampled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureWrapMode {
+    /// Stretch the edge texels outwards.
+    ///
+    /// This is the default, and what egui expects for its own textures (e.g. the font atlas).
+    ClampToEdge,
 
-    /// Stores outdated OpenGL textures that are yet to be deleted
-    textures_to_destroy: Vec<glow::Texture>,
+    /// Tile the texture.
+    Repeat,
 
-    /// Used to make sure we are destroyed correctly.
-    destroyed: bool,
+    /// Tile the texture, mirroring every other repetition.
+    MirroredRepeat,
 }
 
-/// A callback function that can be used to compose an [`egui::PaintCallback`] for custom rendering
-/// with [`glow`].
-///
-/// The callback is passed, the [`egui::PaintCallbackInfo`] and the [`Painter`] which can be used to
-/// access the OpenGL context.
-///
-/// # Example
-///
-/// See the [`custom3d_glow`](https://github.com/emilk/egui/blob/master/crates/egui_demo_app/src/apps/custom3d_wgpu.rs) demo source for a detailed usage example.
-pub struct CallbackFn {
-    f: Box<dyn Fn(PaintCallbackInfo, &Painter) + Sync + Send>,
+impl Default for TextureWrapMode {
+    fn default() -> Self {
+        Self::ClampToEdge
+    }
 }
 
-impl CallbackFn {
-    pub fn new<F: Fn(PaintCallbackInfo, &Painter) + Sync + Send + 'static>(callback: F) -> Self {
-        let f = Box::new(callback);
-        CallbackFn { f }
+impl TextureWrapMode {
+    fn glow_code(&self) -> u32 {
+        match self {
+            Self::ClampToEdge => glow::CLAMP_TO_EDGE,
+            Self::Repeat => glow::REPEAT,
+            Self::MirroredRepeat => glow::MIRRORED_REPEAT,
+        }
     }
 }
 
-impl Painter {
-    /// Create painter.
-    ///
-    /// Set `pp_fb_extent` to the framebuffer size to enable `sRGB` support on OpenGL ES and WebGL.
-    ///
-    /// Set `shader_prefix` if you want to turn on shader workaround e.g. `"#define APPLY_BRIGHTENING_GAMMA\n"`
-    /// (see <https://github.com/emilk/egui/issues/794>).
+/// How often the mesh data is expected to change, passed on to OpenGL as the usage hint
+/// when the [`Painter`] specifies its vertex and index buffers.
+///
+/// Drivers may use this to decide where to store the buffers.
+/// Has no effect where meshes are copied into persistently mapped buffers instead (OpenGL 4.4+).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BufferUsage {
+    /// `GL_STREAM_DRAW`: the data is replaced every frame.
     ///
-    /// # Errors
-    /// will return `Err` below cases
-    /// * failed to compile shader
-    /// * failed to create postprocess on webgl with `sRGB` support
-    /// * failed to create buffer
-    pub fn new(
-        gl: Arc<glow::Context>,
-        pp_fb_extent: Option<[i32; 2]>,
-        shader_prefix: &str,
-    ) -> Result<Painter, String> {
-        crate::profile_function!();
-        crate::check_for_gl_error_even_in_release!(&gl, "before Painter::new");
+    /// This is the default, and fits most egui apps.
+    Stream,
 
-        let max_texture_side = unsafe { gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) } as usize;
+    /// `GL_DYNAMIC_DRAW`: the data is replaced now and then, and drawn several times in between.
+    Dynamic,
 
-        let shader_version = ShaderVersion::get(&gl);
-        let is_webgl_1 = shader_version == ShaderVersion::Es100;
-        let header = shader_version.version();
-        tracing::debug!("Shader header: {:?}.", header);
-        let srgb_support = gl.supported_extensions().contains("EXT_sRGB");
+    /// `GL_STATIC_DRAW`: the data is rarely replaced, e.g. if you repaint rarely but your meshes are big.
+    Static,
+}
 
-        let (post_process, srgb_support_define) = match (shader_version, srgb_support) {
-            // WebGL2 support sRGB default
-            (ShaderVersion::Es300, _) | (ShaderVersion::Es100, true) => unsafe {
-                // Add sRGB support marker for fragment shader
-                if let Some(size) = pp_fb_extent {
-                    tracing::debug!("WebGL with sRGB enabled. Turning on post processing for linear framebuffer blending.");
-                    // install post process to correct sRGB color:
-                    (
-                        Some(PostProcess::new(
-                            gl.clone(),
-                            shader_prefix,
-                            is_webgl_1,
-                            size,
-                        )?),
-                        "#define SRGB_SUPPORTED",
-                    )
-                } else {
-                    tracing::debug!("WebGL or OpenGL ES detected but PostProcess disabled because dimension is None");
-                    (None, "")
-                }
-            },
+impl Default for BufferUsage {
+    fn default() -> Self {
+        Self::Stream
+    }
+}
 
-            // WebGL1 without sRGB support disable postprocess and use fallback shader
-            (ShaderVersion::Es100, false) => (None, ""),
+impl BufferUsage {
+    fn glow_code(&self) -> u32 {
+        match self {
+            Self::Stream => glow::STREAM_DRAW,
+            Self::Dynamic => glow::DYNAMIC_DRAW,
+            Self::Static => glow::STATIC_DRAW,
+        }
+    }
+}
 
-            // OpenGL 2.1 or above always support sRGB so add sRGB support marker
-            _ => (None, "#define SRGB_SUPPORTED"),
-        };
+/// How a texture should be sampled.
+///
+/// [`egui::epaint::ImageDelta`] only carries a [`TextureFilter`],
+/// so this is how you tell the [`Painter`] about everything else.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TextureOptions {
+    /// How to filter when magnifying (when texels are larger than pixels).
+    pub magnification: TextureFilter,
 
-        unsafe {
-            let vert = compile_shader(
-                &gl,
-                glow::VERTEX_SHADER,
-                &format!(
-                    "{}\n{}\n{}\n{}",
-                    header,
-                    shader_prefix,
-                    shader_version.is_new_shader_interface(),
-                    VERT_SRC
-                ),
-            )?;
-            let frag = compile_shader(
-                &gl,
-                glow::FRAGMENT_SHADER,
-                &format!(
-                    "{}\n{}\n{}\n{}\n{}",
-                    header,
-                    shader_prefix,
-                    srgb_support_define,
-                    shader_version.is_new_shader_interface(),
-                    FRAG_SRC
-                ),
-            )?;
-            let program = link_program(&gl, [vert, frag].iter())?;
-            gl.detach_shader(program, vert);
-            gl.detach_shader(program, frag);
-            gl.delete_shader(vert);
-            gl.delete_shader(frag);
-            let u_screen_size = gl.get_uniform_location(program, "u_screen_size").unwrap();
-            let u_sampler = gl.get_uniform_location(program, "u_sampler").unwrap();
+    /// How to filter when minifying (when texels are smaller than pixels).
+    pub minification: TextureFilter,
 
-            let vbo = gl.create_buffer()?;
+    /// How to sample outside of the `[0, 1]` texture coordinate range.
+    pub wrap_mode: TextureWrapMode,
 
-            let a_pos_loc = gl.get_attrib_location(program, "a_pos").unwrap();
-            let a_tc_loc = gl.get_attrib_location(program, "a_tc").unwrap();
-            let a_srgba_loc = gl.get_attrib_location(program, "a_srgba").unwrap();
+    /// If set, mipmaps are generated on every full upload, and sampled with this filter when minifying
+    /// (e.g. `Some(TextureFilter::Linear)` together with linear `minification` gives trilinear filtering).
+    ///
+    /// This reduces aliasing when a large image is shown scaled down,
+    /// at the cost of about a third more video memory for the texture.
+    /// Partial updates do not regenerate the mipmaps.
+    ///
+    /// On WebGL1 this requires the texture to have power-of-two dimensions.
+    pub mipmap_mode: Option<TextureFilter>,
 
-            let stride = std::mem::size_of::<Vertex>() as i32;
-            let buffer_infos = vec![
-                vao::BufferInfo {
-                    location: a_pos_loc,
-                    vector_size: 2,
-                    data_type: glow::FLOAT,
-                    normalized: false,
-                    stride,
-                    offset: offset_of!(Vertex, pos) as i32,
-                },
-                vao::BufferInfo {
-                    location: a_tc_loc,
-                    vector_size: 2,
-                    data_type: glow::FLOAT,
-                    normalized: false,
-                    stride,
-                    offset: offset_of!(Vertex, uv) as i32,
-                },
-                vao::BufferInfo {
-                    location: a_srgba_loc,
-                    vector_size: 4,
-                    data_type: glow::UNSIGNED_BYTE,
-                    normalized: false,
-                    stride,
-                    offset: offset_of!(Vertex, color) as i32,
-                },
-            ];
-            let vao = crate::vao::VertexArrayObject::new(&gl, vbo, buffer_infos);
+    /// Store the texels as-is (`RGBA8`) instead of as sRGB (`SRGB8_ALPHA8`),
+    /// so that they are not converted from sRGB to linear when sampled.
+    ///
+    /// Use this for data that is already linear, like normal maps.
+    /// Only takes effect on full uploads.
+    ///
+    /// Not supported on WebGL1 without `EXT_sRGB`, where the shader always decodes sRGB.
+    pub linear: bool,
 
-            let element_array_buffer = gl.create_buffer()?;
+    /// The texels have straight (unmultiplied) alpha, e.g. as loaded from a PNG,
+    /// so the fragment shader should premultiply them (in linear space) before blending.
+    ///
+    /// egui expects premultiplied alpha everywhere else, so this only affects meshes painted with this texture
+    /// (e.g. an [`egui::Image`] showing it), and the vertex colors of those meshes are still premultiplied.
+    /// Never set this for the font atlas ([`egui::TextureId::default`]),
+    /// which holds the white texel that all untextured shapes are painted with.
+    pub straight_alpha: bool,
+}
 
-            crate::check_for_gl_error_even_in_release!(&gl, "after Painter::new");
+impl TextureOptions {
+    /// Linear magnification and minification, clamping at the edges.
+    pub const LINEAR: Self = Self {
+        magnification: TextureFilter::Linear,
+        minification: TextureFilter::Linear,
+        wrap_mode: TextureWrapMode::ClampToEdge,
+        mipmap_mode: None,
+        linear: false,
+        straight_alpha: false,
+    };
 
-            Ok(Painter {
-                gl,
-                max_texture_side,
-                program,
-                u_screen_size,
-                u_sampler,
-                is_webgl_1,
-                is_embedded: matches!(shader_version, ShaderVersion::Es100 | ShaderVersion::Es300),
-                vao,
-                srgb_support,
-                post_process,
-                vbo,
-                element_array_buffer,
-                textures: Default::default(),
-                next_native_tex_id: 1 << 32,
-                textures_to_destroy: Vec::new(),
-                destroyed: false,
-            })
+    /// Nearest magnification and minification, clamping at the edges.
+    pub const NEAREST: Self = Self {
+        magnification: TextureFilter::Nearest,
+        minification: TextureFilter::Nearest,
+        wrap_mode: TextureWrapMode::ClampToEdge,
+        mipmap_mode: None,
+        linear: false,
+        straight_alpha: false,
+    };
+
+    fn glow_min_filter(&self) -> u32 {
+        match (self.minification, self.mipmap_mode) {
+            (minification, None) => minification.glow_code(),
+            (TextureFilter::Linear, Some(TextureFilter::Linear)) => glow::LINEAR_MIPMAP_LINEAR,
+            (TextureFilter::Linear, Some(TextureFilter::Nearest)) => glow::LINEAR_MIPMAP_NEAREST,
+            (TextureFilter::Nearest, Some(TextureFilter::Linear)) => glow::NEAREST_MIPMAP_LINEAR,
+            (TextureFilter::Nearest, Some(TextureFilter::Nearest)) => glow::NEAREST_MIPMAP_NEAREST,
         }
     }
+}
 
-    /// Access the shared glow context.
-    pub fn gl(&self) -> &Arc<glow::Context> {
-        &self.gl
+/// An image too large for a single texture, uploaded by [`Painter::set_tiled_texture`] as a grid of textures.
+///
+/// A mesh can only use one texture, so paint it with [`Self::shapes`], e.g. with [`egui::Painter::extend`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TiledTexture {
+    size: [usize; 2],
+    tiles: Vec<TextureTile>,
+}
+
+/// One of the textures of a [`TiledTexture`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTile {
+    /// The texture holding this part of the image.
+    pub id: egui::TextureId,
+
+    /// Which part of the image, as fractions of its size (so that the whole image is `[0, 0] - [1, 1]`).
+    pub rect: Rect,
+}
+
+impl TiledTexture {
+    /// The size of the whole image, in texels.
+    pub fn size(&self) -> [usize; 2] {
+        self.size
     }
 
-    pub fn max_texture_side(&self) -> usize {
-        self.max_texture_side
+    /// The tiles, row by row from the top left.
+    pub fn tiles(&self) -> &[TextureTile] {
+        &self.tiles
     }
 
-    /// The framebuffer we use as an intermediate render target,
-    /// or `None` if we are painting to the screen framebuffer directly.
-    ///
-    /// This is the framebuffer that is bound when [`egui::Shape::Callback`] is called,
-    /// and is where any callbacks should ultimately render onto.
-    ///
-    /// So if in a [`egui::Shape::Callback`] you need to use an offscreen FBO, you should
-    /// then restore to this afterwards with
-    /// `gl.bind_framebuffer(glow::FRAMEBUFFER, painter.intermediate_fbo());`
-    pub fn intermediate_fbo(&self) -> Option<glow::Framebuffer> {
-        self.post_process.as_ref().map(|pp| pp.fbo())
+    /// One textured rectangle per tile, together painting the whole image into `rect`, tinted by `tint`.
+    pub fn shapes(&self, rect: Rect, tint: Color32) -> Vec<egui::Shape> {
+        let uv = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let tile_rect = Rect::from_min_max(
+                    rect.lerp(tile.rect.min.to_vec2()),
+                    rect.lerp(tile.rect.max.to_vec2()),
+                );
+                let mut mesh = Mesh::with_texture(tile.id);
+                mesh.add_rect_with_uv(tile_rect, uv, tint);
+                egui::Shape::mesh(mesh)
+            })
+            .collect()
     }
+}
 
-    unsafe fn prepare_painting(
-        &mut self,
-        [width_in_pixels, height_in_pixels]: [u32; 2],
-        pixels_per_point: f32,
-    ) -> (u32, u32) {
-        self.gl.enable(glow::SCISSOR_TEST);
-        // egui outputs mesh in both winding orders
-        self.gl.disable(glow::CULL_FACE);
-        self.gl.disable(glow::DEPTH_TEST);
+/// Why creating a [`Painter`] (e.g. with [`Painter::new`]),
+/// or a fallible [`Painter`] method (e.g. [`Painter::try_set_texture`]) failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PainterError {
+    /// The painter has already been destroyed with [`Painter::destroy`].
+    Destroyed,
 
-        self.gl.color_mask(true, true, true, true);
+    /// A shader failed to compile.
+    ShaderCompile {
+        /// `"vertex"` or `"fragment"`.
+        stage: &'static str,
 
-        self.gl.enable(glow::BLEND);
-        self.gl
-            .blend_equation_separate(glow::FUNC_ADD, glow::FUNC_ADD);
-        self.gl.blend_func_separate(
-            // egui outputs colors with premultiplied alpha:
-            glow::ONE,
-            glow::ONE_MINUS_SRC_ALPHA,
-            // Less important, but this is technically the correct alpha blend function
-            // when you want to make use of the framebuffer alpha (for screenshots, compositing, etc).
-            glow::ONE_MINUS_DST_ALPHA,
-            glow::ONE,
-        );
+        /// The log from the driver, followed by the numbered source.
+        log: String,
+    },
 
-        if !cfg!(target_arch = "wasm32") {
-            self.gl.enable(glow::FRAMEBUFFER_SRGB);
-            check_for_gl_error!(&self.gl, "FRAMEBUFFER_SRGB");
+    /// The shader program failed to link, with the log from the driver followed by the numbered sources,
+    /// or it lacks a uniform or attribute the painter needs (see [`Painter::new_with_shader_patch`]).
+    ProgramLink(String),
+
+    /// A vertex or index buffer could not be created.
+    BufferCreation(String),
+
+    /// The intermediate framebuffer for post-processing could not be set up.
+    PostProcess(String),
+
+    /// A texture upload was rejected, e.g. because the image is empty or larger than [`Painter::max_texture_side`].
+    TextureUpload(String),
+}
+
+impl std::fmt::Display for PainterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Destroyed => f.write_str("the egui glow painter has already been destroyed"),
+            Self::ShaderCompile { stage, log } => {
+                write!(f, "failed to compile the {} shader: {}", stage, log)
+            }
+            Self::ProgramLink(log) => write!(f, "failed to link the shader program: {}", log),
+            Self::BufferCreation(err) => write!(f, "failed to create a buffer: {}", err),
+            Self::PostProcess(err) => write!(f, "failed to set up post-processing: {}", err),
+            Self::TextureUpload(err) => write!(f, "failed to upload a texture: {}", err),
         }
+    }
+}
 
-        let width_in_points = width_in_pixels as f32 / pixels_per_point;
-        let height_in_points = height_in_pixels as f32 / pixels_per_point;
+impl std::error::Error for PainterError {}
 
-        self.gl
-            .viewport(0, 0, width_in_pixels as i32, height_in_pixels as i32);
-        self.gl.use_program(Some(self.program));
+/// A texture known to the [`Painter`], together with how it should be sampled.
+struct PainterTexture {
+    texture: glow::Texture,
 
-        self.gl
-            .uniform_2_f32(Some(&self.u_screen_size), width_in_points, height_in_points);
-        self.gl.uniform_1_i32(Some(&self.u_sampler), 0);
-        self.gl.active_texture(glow::TEXTURE0);
+    /// `None` for native textures whose sampler state we should leave alone.
+    options: Option<TextureOptions>,
 
-        self.vao.bind(&self.gl);
-        self.gl
-            .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.element_array_buffer));
+    /// The options last set on the GL texture, or `None` if we never set any.
+    applied_options: Option<TextureOptions>,
 
-        check_for_gl_error!(&self.gl, "prepare_painting");
+    /// `None` for native textures of unknown size.
+    size: Option<[usize; 2]>,
 
-        (width_in_pixels, height_in_pixels)
-    }
+    /// The GL internal format (e.g. `glow::SRGB8_ALPHA8`), or `None` for native textures of unknown format.
+    internal_format: Option<u32>,
 
-    /// You are expected to have cleared the color buffer before calling this.
-    pub fn paint_and_update_textures(
-        &mut self,
-        screen_size_px: [u32; 2],
-        pixels_per_point: f32,
-        clipped_primitives: &[egui::ClippedPrimitive],
-        textures_delta: &egui::TexturesDelta,
-    ) {
-        crate::profile_function!();
-        for (id, image_delta) in &textures_delta.set {
-            self.set_texture(*id, image_delta);
-        }
+    /// This is a font atlas stored as single-channel `R8` coverage,
+    /// which the fragment shader expands to premultiplied white.
+    is_r8_font: bool,
 
-        self.paint_primitives(screen_size_px, pixels_per_point, clipped_primitives);
+    /// This is a font atlas stored as `RG8`, which the sampler expands to premultiplied white.
+    is_swizzled_font: bool,
 
-        for &id in &textures_delta.free {
-            self.free_texture(id);
+    /// Set with [`Painter::set_texture_anisotropy`].
+    anisotropy: Option<f32>,
+}
+
+impl PainterTexture {
+    fn native(texture: glow::Texture) -> Self {
+        Self {
+            texture,
+            options: None,
+            applied_options: None,
+            size: None,
+            internal_format: None,
+            is_r8_font: false,
+            is_swizzled_font: false,
+            anisotropy: None,
         }
     }
 
-    /// Main entry-point for painting a frame.
-    ///
-    /// You should call `target.clear_color(..)` before
-    /// and `target.finish()` after this.
-    ///
-    /// The following OpenGL features will be set:
-    /// - Scissor test will be enabled
-    /// - Cull face will be disabled
-    /// - Blend will be enabled
+    /// Remember the `options` of an upload, and return whether they need to be set on the GL texture.
     ///
-    /// The scissor area and blend parameters will be changed.
-    ///
-    /// As well as this, the following objects will be unset:
-    /// - Vertex Buffer
-    /// - Element Buffer
-    /// - Texture (and active texture will be set to 0)
-    /// - Program
-    ///
-    /// Please be mindful of these effects when integrating into your program, and also be mindful
-    /// of the effects your program might have on this code. Look at the source if in doubt.
-    pub fn paint_primitives(
-        &mut self,
-        screen_size_px: [u32; 2],
-        pixels_per_point: f32,
-        clipped_primitives: &[egui::ClippedPrimitive],
-    ) {
+    /// That is on full uploads, which may have reset the texture, and whenever the options changed,
+    /// e.g. when the same texture is uploaded again with a different [`egui::epaint::ImageDelta::filter`].
+    fn set_upload_options(&mut self, options: TextureOptions, full_upload: bool) -> bool {
+        self.options = Some(options);
+        let apply = full_upload || self.applied_options != Some(options);
+        self.applied_options = Some(options);
+        apply
+    }
+}
+
+/// The framebuffer [`Painter::render_to_texture`] paints into.
+#[derive(Clone, Copy)]
+struct RenderTarget {
+    fbo: glow::Framebuffer,
+    texture: glow::Texture,
+    size: [u32; 2],
+}
+
+/// How texels are laid out when uploaded, and how the texture stores them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct UploadFormat {
+    internal_format: u32,
+    src_format: u32,
+    src_type: u32,
+    bytes_per_texel: usize,
+}
+
+impl UploadFormat {
+    /// Single-channel font coverage.
+    const R8: Self = Self {
+        internal_format: glow::R8,
+        src_format: glow::RED,
+        src_type: glow::UNSIGNED_BYTE,
+        bytes_per_texel: 1,
+    };
+
+    /// The font atlas as linear color and alpha, swizzled to `RRRG` by [`swizzle_font_texture`].
+    const RG8_FONT: Self = Self {
+        internal_format: glow::RG8,
+        src_format: glow::RG,
+        src_type: glow::UNSIGNED_BYTE,
+        bytes_per_texel: 2,
+    };
+
+    /// HDR RGBA from half-precision floats.
+    const RGBA16F_FROM_F16: Self = Self {
+        internal_format: glow::RGBA16F,
+        src_format: glow::RGBA,
+        src_type: glow::HALF_FLOAT,
+        bytes_per_texel: 8,
+    };
+
+    /// HDR RGBA from single-precision floats.
+    const RGBA16F_FROM_F32: Self = Self {
+        internal_format: glow::RGBA16F,
+        src_format: glow::RGBA,
+        src_type: glow::FLOAT,
+        bytes_per_texel: 16,
+    };
+}
+
+/// Is this a floating point internal format, i.e. one that holds linear values?
+fn is_float_format(internal_format: Option<u32>) -> bool {
+    matches!(
+        internal_format,
+        Some(glow::RGBA16F | glow::RGBA32F | glow::RGB16F | glow::RGB32F)
+    )
+}
+
+/// Convert the coverage of a font image to texels for uploading, replacing the contents of `texels`.
+///
+/// With `r8` we only write the alpha, otherwise premultiplied sRGBA.
+/// Reusing the same `texels` avoids reallocating it for every update of the font atlas.
+fn font_texels(image: &egui::FontImage, gamma: f32, format: UploadFormat, texels: &mut Vec<u8>) {
+    texels.clear();
+    match format {
+        UploadFormat::R8 => {
+            // The texels are premultiplied white, so the alpha says it all:
+            texels.extend(image.srgba_pixels(gamma).map(|a| a.a()));
+        }
+        UploadFormat::RG8_FONT => {
+            // What an sRGBA texture sampler would return for the color, then the alpha:
+            texels.extend(image.srgba_pixels(gamma).flat_map(|a| {
+                let linear = egui::epaint::color::linear_f32_from_gamma_u8(a.a());
+                [
+                    egui::epaint::color::linear_u8_from_linear_f32(linear),
+                    a.a(),
+                ]
+            }));
+        }
+        _ => {
+            texels.extend(image.srgba_pixels(gamma).flat_map(|a| a.to_array()));
+        }
+    }
+}
+
+/// The last value a uniform was set to, so we can skip setting it to the same value again.
+///
+/// Uniform values are part of the program state, so other programs (e.g. in callbacks) do not invalidate this.
+struct CachedUniform<T>(Option<T>);
+
+impl<T> Default for CachedUniform<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T: PartialEq> CachedUniform<T> {
+    /// Returns `true` if `value` differs from the last one, i.e. if the uniform needs to be set.
+    fn update(&mut self, value: T) -> bool {
+        if self.0.as_ref() == Some(&value) {
+            false
+        } else {
+            self.0 = Some(value);
+            true
+        }
+    }
+}
+
+/// Make the texture currently bound to `TEXTURE_2D`, stored as [`UploadFormat::RG8_FONT`],
+/// sample as premultiplied white like an sRGBA font texture would.
+///
+/// The color channel is already linear, so no sRGB decoding is needed.
+unsafe fn swizzle_font_texture(gl: &glow::Context) {
+    let red = glow::RED as i32;
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_SWIZZLE_R, red);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_SWIZZLE_G, red);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_SWIZZLE_B, red);
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_SWIZZLE_A,
+        glow::GREEN as i32,
+    );
+}
+
+/// Set the sampler parameters of the texture currently bound to `TEXTURE_2D`.
+///
+/// `lod_bias` is only set on mipmapped textures, and only if non-zero (the GL default),
+/// since `TEXTURE_LOD_BIAS` doesn't exist on OpenGL ES and WebGL.
+unsafe fn apply_texture_options(gl: &glow::Context, options: &TextureOptions, lod_bias: f32) {
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_MAG_FILTER,
+        options.magnification.glow_code() as i32,
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_MIN_FILTER,
+        options.glow_min_filter() as i32,
+    );
+
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_WRAP_S,
+        options.wrap_mode.glow_code() as i32,
+    );
+    gl.tex_parameter_i32(
+        glow::TEXTURE_2D,
+        glow::TEXTURE_WRAP_T,
+        options.wrap_mode.glow_code() as i32,
+    );
+
+    if options.mipmap_mode.is_some() && lod_bias != 0.0 {
+        gl.tex_parameter_f32(glow::TEXTURE_2D, glow::TEXTURE_LOD_BIAS, lod_bias);
+    }
+    check_for_gl_error!(gl, "tex_parameter");
+}
+
+/// The sampling state of a texture, which [`Painter`] caches a sampler object for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    mag_filter: u32,
+    min_filter: u32,
+    wrap_mode: u32,
+
+    /// The bits of the anisotropic filtering level, or `None` to leave it at the default.
+    anisotropy: Option<u32>,
+}
+
+impl SamplerKey {
+    fn new(options: &TextureOptions, anisotropy: Option<f32>) -> Self {
+        Self {
+            mag_filter: options.magnification.glow_code(),
+            min_filter: options.glow_min_filter(),
+            wrap_mode: options.wrap_mode.glow_code(),
+            anisotropy: anisotropy.map(f32::to_bits),
+        }
+    }
+
+    fn is_mipmapped(&self) -> bool {
+        self.min_filter != glow::NEAREST && self.min_filter != glow::LINEAR
+    }
+}
+
+/// A [`Primitive`], where a mesh may be several consecutive meshes merged into one.
+enum BatchedPrimitive<'a> {
+    Mesh(Cow<'a, Mesh>),
+    Callback(&'a egui::PaintCallback),
+}
+
+/// Merge runs of consecutive meshes that share both texture and clip rectangle,
+/// so that each run is uploaded and drawn with a single draw call
+/// (e.g. for text-heavy panels, where each glyph run is its own mesh).
+///
+/// With `damage`, primitives that would paint nothing inside it are left out.
+fn batch_primitives(
+    clipped_primitives: &[egui::ClippedPrimitive],
+    damage: Option<Rect>,
+) -> Vec<(Rect, BatchedPrimitive<'_>)> {
+    crate::profile_function!();
+
+    let mut batches: Vec<(Rect, BatchedPrimitive<'_>)> =
+        Vec::with_capacity(clipped_primitives.len());
+    for clipped_primitive in clipped_primitives {
+        if let Some(damage) = damage {
+            if !is_damaged(clipped_primitive, damage) {
+                continue;
+            }
+        }
+        let egui::ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } = clipped_primitive;
+        match primitive {
+            Primitive::Mesh(mesh) => {
+                if let Some((batch_clip_rect, BatchedPrimitive::Mesh(batch))) = batches.last_mut() {
+                    if batch_clip_rect == clip_rect && batch.texture_id == mesh.texture_id {
+                        batch.to_mut().append_ref(mesh);
+                        continue;
+                    }
+                }
+                batches.push((*clip_rect, BatchedPrimitive::Mesh(Cow::Borrowed(mesh))));
+            }
+            Primitive::Callback(callback) => {
+                batches.push((*clip_rect, BatchedPrimitive::Callback(callback)));
+            }
+        }
+    }
+    batches
+}
+
+/// The constant `a_offset` of each step of [`Painter::paint_instanced_mesh`] without instanced drawing,
+/// and whether to draw the mesh with it. The last step sets it back to zero for the meshes painted after.
+fn offset_steps_without_instancing(
+    offsets: &[egui::Vec2],
+) -> impl Iterator<Item = (egui::Vec2, bool)> + '_ {
+    offsets
+        .iter()
+        .map(|offset| (*offset, true))
+        .chain(std::iter::once((egui::Vec2::ZERO, false)))
+}
+
+/// Whether the primitive paints anything inside `damage`.
+fn is_damaged(clipped_primitive: &egui::ClippedPrimitive, damage: Rect) -> bool {
+    let clip_rect = clipped_primitive.clip_rect.intersect(damage);
+    if !clip_rect.is_positive() {
+        return false;
+    }
+    match &clipped_primitive.primitive {
+        Primitive::Mesh(mesh) => mesh.calc_bounds().intersects(clip_rect),
+        Primitive::Callback(callback) => callback.rect.intersects(clip_rect),
+    }
+}
+
+/// The smallest rect containing all of `damage`, or [`Rect::NOTHING`] if it is empty.
+fn damage_bounds(damage: &[Rect]) -> Rect {
+    damage
+        .iter()
+        .fold(Rect::NOTHING, |bounds, rect| bounds.union(*rect))
+}
+
+/// Limit a [`Painter::set_max_texture_side`] to what the driver supports, and to at least one texel.
+fn clamp_max_texture_side(max_texture_side: usize, gl_max_texture_side: usize) -> usize {
+    max_texture_side.min(gl_max_texture_side).max(1)
+}
+
+/// The `pixels_per_point` to paint with after [`Painter::set_render_scale`],
+/// where `None` is for primitives that are already in pixels and are not scaled at all.
+fn scaled_pixels_per_point(pixels_per_point: Option<f32>, render_scale: f32) -> f32 {
+    pixels_per_point.map_or(1.0, |pixels_per_point| pixels_per_point * render_scale)
+}
+
+/// Draw `count` indices as triangles, starting at `first_index` of the bound element array buffer.
+unsafe fn draw_triangles(gl: &glow::Context, base_vertex: i32, first_index: usize, count: usize) {
+    let offset = (first_index * std::mem::size_of::<u32>()) as i32;
+    if base_vertex == 0 {
+        gl.draw_elements(glow::TRIANGLES, count as i32, glow::UNSIGNED_INT, offset);
+    } else {
+        gl.draw_elements_base_vertex(
+            glow::TRIANGLES,
+            count as i32,
+            glow::UNSIGNED_INT,
+            offset,
+            base_vertex,
+        );
+    }
+}
+
+/// Turn pixels read with `glReadPixels`, which starts at the bottom row, into an image that starts at the top.
+fn image_from_bottom_up([w, h]: [usize; 2], mut pixels: Vec<Color32>) -> egui::ColorImage {
+    for y in 0..h / 2 {
+        let (upper, lower) = pixels.split_at_mut((h - 1 - y) * w);
+        upper[y * w..(y + 1) * w].swap_with_slice(&mut lower[..w]);
+    }
+    egui::ColorImage {
+        size: [w, h],
+        pixels,
+    }
+}
+
+/// Append a triangle fan covering `rect` with rounded corners, for writing into the stencil buffer.
+fn add_rounded_rect(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    rect: Rect,
+    rounding: egui::Rounding,
+) {
+    let mut points = Vec::new();
+    egui::epaint::tessellator::path::rounded_rectangle(&mut points, rect, rounding);
+
+    let first_vertex = vertices.len() as u32;
+    vertices.extend(points.iter().map(|&pos| Vertex {
+        pos,
+        uv: egui::epaint::WHITE_UV,
+        color: Color32::WHITE,
+    }));
+    // The outline is convex, so a fan around the first point covers it:
+    for i in 1..points.len().saturating_sub(1) as u32 {
+        indices.extend([first_vertex, first_vertex + i, first_vertex + i + 1]);
+    }
+}
+
+/// Replace the stencil buffer within the current scissor rectangle with the given triangles,
+/// and set up the stencil test to only pass inside them.
+unsafe fn write_clip_shape(gl: &glow::Context, base_vertex: i32, first_index: usize, count: usize) {
+    gl.stencil_mask(0xff);
+    gl.clear_stencil(0);
+    gl.clear(glow::STENCIL_BUFFER_BIT);
+
+    gl.enable(glow::STENCIL_TEST);
+    gl.stencil_func(glow::ALWAYS, 1, 0xff);
+    gl.stencil_op(glow::KEEP, glow::KEEP, glow::REPLACE);
+    gl.color_mask(false, false, false, false);
+    draw_triangles(gl, base_vertex, first_index, count);
+    gl.color_mask(true, true, true, true);
+
+    gl.stencil_func(glow::EQUAL, 1, 0xff);
+    gl.stencil_op(glow::KEEP, glow::KEEP, glow::KEEP);
+}
+
+/// The default of [`Painter::set_color_transform`].
+#[rustfmt::skip]
+const IDENTITY_MATRIX: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// The blend function egui expects, see [`Painter::set_blend_func`].
+const DEFAULT_BLEND_FUNC: [u32; 4] = [
+    // egui outputs colors with premultiplied alpha:
+    glow::ONE,
+    glow::ONE_MINUS_SRC_ALPHA,
+    // Less important, but this is technically the correct alpha blend function
+    // when you want to make use of the framebuffer alpha (for screenshots, compositing, etc).
+    glow::ONE_MINUS_DST_ALPHA,
+    glow::ONE,
+];
+
+/// Paints callbacks of one type, see [`Painter::register_callback_handler`].
+type CallbackHandler =
+    Box<dyn Fn(&(dyn Any + Send + Sync), PaintCallbackInfo, &Painter) + Send + Sync>;
+
+/// An OpenGL painter using [`glow`].
+///
+/// This is responsible for painting egui and managing egui textures.
+/// You can access the underlying [`glow::Context`] with [`Self::gl`].
+///
+/// This struct must be destroyed with [`Painter::destroy`] before dropping, to ensure OpenGL
+/// objects have been properly deleted and are not leaked.
+pub struct Painter {
+    gl: Arc<glow::Context>,
+
+    /// `MAX_TEXTURE_SIZE`, or lower with [`Self::set_max_texture_side`].
+    max_texture_side: usize,
+
+    /// `MAX_TEXTURE_SIZE`.
+    gl_max_texture_side: usize,
+
+    /// `None` if anisotropic filtering is not supported.
+    max_anisotropy: Option<f32>,
+
+    /// Set with [`Self::set_lod_bias`]. Always zero on OpenGL ES and WebGL.
+    lod_bias: f32,
+
+    program: glow::Program,
+    u_screen_size: glow::UniformLocation,
+    u_sampler: glow::UniformLocation,
+    screen_size_in_points: CachedUniform<[f32; 2]>,
+    sampler: CachedUniform<i32>,
+    u_font_r8: Option<glow::UniformLocation>,
+    u_straight_alpha: Option<glow::UniformLocation>,
+
+    /// Only there with a float intermediate framebuffer, see [`Self::set_hdr_scale`].
+    u_hdr_scale: Option<glow::UniformLocation>,
+    hdr_scale_uniform: CachedUniform<f32>,
+
+    /// Set with [`Self::set_hdr_scale`].
+    hdr_scale: f32,
+
+    u_dither: Option<glow::UniformLocation>,
+    dither_uniform: CachedUniform<bool>,
+
+    /// Set with [`Self::set_dithering`].
+    dithering: bool,
+
+    u_overdraw: Option<glow::UniformLocation>,
+    overdraw_uniform: CachedUniform<bool>,
+
+    /// Set with [`Self::set_overdraw_debug`].
+    overdraw_debug: bool,
+
+    /// Not in the minimal shader of [`Self::new_with_fallback_shader`].
+    u_color_transform: Option<glow::UniformLocation>,
+    color_transform_uniform: CachedUniform<[f32; 16]>,
+
+    /// Set with [`Self::set_color_transform`].
+    color_transform: [f32; 16],
+
+    /// Set with [`Self::set_clip_rounding`].
+    clip_rounding: ClipRounding,
+
+    /// Clip rects in pixels, see [`Self::push_pixel_clip`].
+    pixel_clips: Vec<Rect>,
+
+    /// The bounds of the damage of the frame being painted, see [`Self::paint_primitives_with_damage`].
+    damage: Option<Rect>,
+
+    /// See [`Self::render_target_size`].
+    render_target_size: Option<[u32; 2]>,
+
+    /// Set with [`Self::set_texture_registry`].
+    texture_registry: Option<Arc<TextureRegistry>>,
+
+    /// Created by [`Self::render_to_texture`].
+    render_target: Option<RenderTarget>,
+
+    /// Set with [`Self::set_viewport_offset`].
+    viewport_offset: [i32; 2],
+
+    /// Set with [`Self::set_render_scale`].
+    render_scale: f32,
+
+    /// Set with [`Self::set_wireframe`].
+    wireframe: bool,
+
+    /// Created by [`Self::texture_sender`].
+    texture_queue: Option<TextureQueue>,
+
+    is_webgl_1: bool,
+    is_embedded: bool,
+
+    /// Store the font atlas as single-channel `R8` instead of RGBA.
+    r8_font_texture: bool,
+
+    /// `TEXTURE_SWIZZLE_*` is supported, so [`Self::set_font_swizzle`] can take effect.
+    font_swizzle_supported: bool,
+
+    /// Set with [`Self::set_font_swizzle`].
+    font_swizzle: bool,
+
+    /// Sample textures through [`Self::samplers`] instead of their own texture parameters.
+    samplers_supported: bool,
+
+    /// One sampler object per sampling state in use, created on demand by [`Self::sampler`].
+    samplers: HashMap<SamplerKey, glow::Sampler>,
+
+    vao: crate::vao::VertexArrayObject,
+
+    /// WebGL1 has the `EXT_sRGB` extension, for sRGB textures.
+    ext_srgb: bool,
+
+    /// See [`Self::srgb_support`].
+    srgb_shader: bool,
+    post_process: Option<PostProcess>,
+    vbo: StreamBuffer,
+    element_array_buffer: StreamBuffer,
+
+    /// Set with [`Self::set_buffer_usage`].
+    buffer_usage: BufferUsage,
+
+    /// Set with [`Self::set_framebuffer_srgb`].
+    framebuffer_srgb: bool,
+
+    /// `[src_rgb, dst_rgb, src_alpha, dst_alpha]`, set with [`Self::set_blend_func`].
+    blend_func: [u32; 4],
+
+    /// Set with [`Self::set_preserve_gl_state`].
+    preserve_gl_state: bool,
+
+    /// Set with [`Self::set_preserve_scissor`].
+    preserve_scissor: bool,
+
+    /// Set with [`Self::set_present_intermediate`].
+    present_intermediate: bool,
+
+    /// Set with [`Self::set_rounded_clip_rects`].
+    rounded_clip_rects: Vec<(Rect, egui::Rounding)>,
+
+    /// Used instead of streaming each mesh into [`Self::vbo`] and [`Self::element_array_buffer`]
+    /// where supported (OpenGL 4.4+), and as long as a frame's meshes fit.
+    persistent_buffer: Option<PersistentMeshBuffer>,
+
+    textures: HashMap<egui::TextureId, PainterTexture>,
+
+    /// See [`Self::fallback_texture`].
+    fallback_texture: Option<glow::Texture>,
+
+    /// The texture we last bound while painting meshes, so we can skip re-binding it.
+    /// Reset by [`Self::prepare_painting`], e.g. after a callback that may have bound something else.
+    bound_texture: Option<glow::Texture>,
+
+    /// The next id for [`Self::register_native_texture`] and [`Self::set_tiled_texture`].
+    next_native_tex_id: u64,
+
+    /// Set with [`Self::set_font_gamma`], or `None` to pick one based on the platform.
+    font_gamma: Option<f32>,
+
+    /// Scratch space for converting the font atlas, kept to avoid reallocating it on every update.
+    font_texels: Vec<u8>,
+
+    /// Stores outdated OpenGL textures that are yet to be deleted
+    textures_to_destroy: Vec<glow::Texture>,
+
+    /// Errors returned by [`CallbackFn::new_fallible`] callbacks during the last frame.
+    callback_errors: Vec<String>,
+
+    /// Counted since the last frame, until it's done and moved into [`Self::last_frame_stats`].
+    frame_stats: PaintStats,
+    last_frame_stats: PaintStats,
+
+    /// For paint callbacks that are not a [`CallbackFn`], keyed by their type.
+    callback_handlers: HashMap<TypeId, CallbackHandler>,
+
+    /// How the vertices of our meshes are laid out, for the VAOs of [`Self::register_instanced_mesh`].
+    vertex_attributes: Vec<vao::BufferInfo>,
+
+    /// The per-instance offset of [`Self::paint_instanced_mesh`], or `None` if a patched shader doesn't have it.
+    a_offset_loc: Option<u32>,
+
+    /// Draw all instances with one `glDrawElementsInstanced`, rather than one draw call each.
+    instancing_supported: bool,
+
+    instanced_meshes: HashMap<InstancedMeshId, InstancedMesh>,
+
+    next_instanced_mesh_id: u64,
+
+    /// Can [`Self::request_screenshot`] read back the screen without stalling?
+    async_screenshots: bool,
+
+    /// Started by [`Self::request_screenshot`], and not yet collected by [`Self::try_get_screenshot`].
+    screenshots: HashMap<ScreenshotToken, Screenshot>,
+
+    next_screenshot_token: u64,
+
+    /// Set with [`Self::set_retain_texture_data`].
+    retain_texture_data: bool,
+
+    /// CPU-side copies of the textures we uploaded, if [`Self::retain_texture_data`].
+    retained_textures: HashMap<egui::TextureId, RetainedTexture>,
+
+    /// What we were created with, so that [`Self::recreate`] can do it again.
+    config: PainterConfig,
+
+    /// So that [`Self::recompile_program`] can compile the program again.
+    shader_sources: ShaderSources,
+
+    /// Set by [`Self::on_context_lost`], until [`Self::recreate`].
+    context_lost: bool,
+
+    /// Used to make sure we are destroyed correctly.
+    destroyed: bool,
+
+    /// Set with [`Self::set_suppress_leak_warning`].
+    suppress_leak_warning: bool,
+
+    /// Set with [`Self::set_destroy_on_drop`].
+    destroy_on_drop: bool,
+}
+
+/// A CPU-side copy of a texture, see [`Painter::set_retain_texture_data`].
+struct RetainedTexture {
+    size: [usize; 2],
+    options: TextureOptions,
+    format: UploadFormat,
+    data: Vec<u8>,
+}
+
+/// Update the retained copy of a texture with an upload of `size` texels at `pos`, or the whole texture if `pos` is `None`.
+///
+/// Partial updates of textures we have no copy of (or of a different format) are ignored.
+fn retain_texels(
+    retained_textures: &mut HashMap<egui::TextureId, RetainedTexture>,
+    tex_id: egui::TextureId,
+    pos: Option<[usize; 2]>,
+    [w, h]: [usize; 2],
+    options: TextureOptions,
+    format: UploadFormat,
+    data: &[u8],
+) {
+    match pos {
+        None => {
+            retained_textures.insert(
+                tex_id,
+                RetainedTexture {
+                    size: [w, h],
+                    options,
+                    format,
+                    data: data.to_vec(),
+                },
+            );
+        }
+        Some([x, y]) => {
+            let retained = match retained_textures.get_mut(&tex_id) {
+                Some(retained) if retained.format == format => retained,
+                _ => return,
+            };
+            let [tex_w, tex_h] = retained.size;
+            if x + w > tex_w || y + h > tex_h {
+                return;
+            }
+            retained.options = options;
+            let row_len = w * format.bytes_per_texel;
+            for (row, src) in data.chunks_exact(row_len).enumerate() {
+                let start = ((y + row) * tex_w + x) * format.bytes_per_texel;
+                retained.data[start..start + row_len].copy_from_slice(src);
+            }
+        }
+    }
+}
+
+/// The arguments a [`Painter`] was created with.
+struct PainterConfig {
+    pp_fb_extent: Option<[i32; 2]>,
+    shader_prefix: String,
+
+    /// See [`Painter::new_with_intermediate_format`].
+    intermediate_format: Option<u32>,
+
+    /// See [`Painter::new_with_shader_version`].
+    shader_version: Option<ShaderVersion>,
+
+    /// See [`Painter::new_with_shader_patch`].
+    patch_shader_source: Option<Arc<dyn Fn(u32, String) -> String + Send + Sync>>,
+
+    /// Compile the dithering code into the fragment shader, see [`Painter::set_dithering`].
+    dithering: bool,
+
+    /// Compile the overdraw visualization into the fragment shader, see [`Painter::set_overdraw_debug`].
+    overdraw_debug: bool,
+
+    /// Use the minimal fragment shader, see [`Painter::new_with_fallback_shader`].
+    fallback_shader: bool,
+}
+
+impl PainterConfig {
+    fn new(pp_fb_extent: Option<[i32; 2]>, shader_prefix: &str) -> Self {
+        Self {
+            pp_fb_extent,
+            shader_prefix: shader_prefix.to_owned(),
+            intermediate_format: None,
+            shader_version: None,
+            patch_shader_source: None,
+            dithering: false,
+            overdraw_debug: false,
+            fallback_shader: false,
+        }
+    }
+
+    /// The defines for the optional parts of the fragment shader.
+    fn fragment_defines(&self) -> String {
+        let mut defines = String::new();
+        if self.dithering {
+            defines += "#define DITHER\n";
+        }
+        if self.overdraw_debug {
+            defines += "#define OVERDRAW_DEBUG\n";
+        }
+        defines
+    }
+}
+
+/// The shader sources of a [`Painter`], without the defines of [`PainterConfig::fragment_defines`],
+/// so that the program can be compiled again when those change.
+struct ShaderSources {
+    vertex: String,
+    fragment_header: String,
+}
+
+impl ShaderSources {
+    /// Compile and link the program, with the vertex attributes at the locations [`Painter`] expects.
+    unsafe fn compile(
+        &self,
+        gl: &glow::Context,
+        config: &PainterConfig,
+    ) -> Result<glow::Program, PainterError> {
+        compile_program(gl, &self.complete(config), |_| ())
+    }
+
+    /// The complete vertex and fragment shader sources for `config`,
+    /// after [`Painter::new_with_shader_patch`].
+    fn complete(&self, config: &PainterConfig) -> [String; 2] {
+        let vert_src = self.vertex.clone();
+        let frag_src = format!(
+            "{}\n{}\n{}",
+            self.fragment_header,
+            config.fragment_defines(),
+            if config.fallback_shader {
+                FRAG_MINIMAL_SRC
+            } else {
+                FRAG_SRC
+            }
+        );
+        match &config.patch_shader_source {
+            Some(patch) => [
+                patch(glow::VERTEX_SHADER, vert_src),
+                patch(glow::FRAGMENT_SHADER, frag_src),
+            ],
+            None => [vert_src, frag_src],
+        }
+    }
+}
+
+/// Compile and link the program from the complete sources of [`ShaderSources::complete`],
+/// with the vertex attributes at the locations [`Painter`] expects.
+///
+/// `before_link` is called with the program just before it is linked.
+unsafe fn compile_program(
+    gl: &glow::Context,
+    [vert_src, frag_src]: &[String; 2],
+    before_link: impl FnOnce(glow::Program),
+) -> Result<glow::Program, PainterError> {
+    let vert = compile_shader(gl, glow::VERTEX_SHADER, vert_src).map_err(|log| {
+        PainterError::ShaderCompile {
+            stage: "vertex",
+            log,
+        }
+    })?;
+    let frag = match compile_shader(gl, glow::FRAGMENT_SHADER, frag_src) {
+        Ok(frag) => frag,
+        Err(log) => {
+            gl.delete_shader(vert);
+            return Err(PainterError::ShaderCompile {
+                stage: "fragment",
+                log,
+            });
+        }
+    };
+    // Keep `a_pos` at location 0, which compatibility profiles require to be an enabled array,
+    // and which `a_offset` mostly isn't. The others are fixed too, so a recompiled program fits the same VAOs:
+    let program = link_program_with_attributes(
+        gl,
+        &[(vert, vert_src.as_str()), (frag, frag_src.as_str())],
+        &[(0, "a_pos"), (1, "a_tc"), (2, "a_srgba"), (3, "a_offset")],
+        before_link,
+    );
+    if let Ok(program) = program {
+        gl.detach_shader(program, vert);
+        gl.detach_shader(program, frag);
+    }
+    gl.delete_shader(vert);
+    gl.delete_shader(frag);
+    program.map_err(PainterError::ProgramLink)
+}
+
+/// The uniform locations of the program of a [`Painter`].
+struct UniformLocations {
+    u_screen_size: glow::UniformLocation,
+    u_sampler: glow::UniformLocation,
+    u_font_r8: Option<glow::UniformLocation>,
+    u_straight_alpha: Option<glow::UniformLocation>,
+    u_hdr_scale: Option<glow::UniformLocation>,
+    u_dither: Option<glow::UniformLocation>,
+    u_overdraw: Option<glow::UniformLocation>,
+    u_color_transform: Option<glow::UniformLocation>,
+}
+
+impl UniformLocations {
+    /// Fails if `u_screen_size` or `u_sampler` is missing, e.g. because a shader patch removed it.
+    unsafe fn get(gl: &glow::Context, program: glow::Program) -> Result<Self, PainterError> {
+        let required = |name| {
+            gl.get_uniform_location(program, name).ok_or_else(|| {
+                PainterError::ProgramLink(format!("the shader program has no uniform {}", name))
+            })
+        };
+        Ok(Self {
+            u_screen_size: required("u_screen_size")?,
+            u_sampler: required("u_sampler")?,
+            u_font_r8: gl.get_uniform_location(program, "u_font_r8"),
+            u_straight_alpha: gl.get_uniform_location(program, "u_straight_alpha"),
+            u_hdr_scale: gl.get_uniform_location(program, "u_hdr_scale"),
+            u_dither: gl.get_uniform_location(program, "u_dither"),
+            u_overdraw: gl.get_uniform_location(program, "u_overdraw"),
+            u_color_transform: gl.get_uniform_location(program, "u_color_transform"),
+        })
+    }
+}
+
+/// The location of the vertex attribute `name`, which the painter can't do without.
+unsafe fn required_attrib_location(
+    gl: &glow::Context,
+    program: glow::Program,
+    name: &str,
+) -> Result<u32, PainterError> {
+    gl.get_attrib_location(program, name).ok_or_else(|| {
+        PainterError::ProgramLink(format!("the shader program has no attribute {}", name))
+    })
+}
+
+/// A callback function that can be used to compose an [`egui::PaintCallback`] for custom rendering
+/// with [`glow`].
+///
+/// The callback is passed, the [`egui::PaintCallbackInfo`] and the [`Painter`] which can be used to
+/// access the OpenGL context.
+///
+/// # Example
+///
+/// See the [`custom3d_glow`](https://github.com/emilk/egui/blob/master/crates/egui_demo_app/src/apps/custom3d_wgpu.rs) demo source for a detailed usage example.
+pub struct CallbackFn {
+    f: Box<dyn Fn(PaintCallbackInfo, &Painter) -> Result<(), String> + Sync + Send>,
+
+    /// See [`Self::skip_state_restore`].
+    skip_state_restore: bool,
+}
+
+impl CallbackFn {
+    pub fn new<F: Fn(PaintCallbackInfo, &Painter) + Sync + Send + 'static>(callback: F) -> Self {
+        let f = Box::new(move |info, painter: &Painter| {
+            callback(info, painter);
+            Ok(())
+        });
+        CallbackFn {
+            f,
+            skip_state_restore: false,
+        }
+    }
+
+    /// Like [`Self::new`], but the callback can report that it failed (e.g. to bind its shader).
+    ///
+    /// The error is logged, and collected in [`Painter::callback_errors`].
+    pub fn new_fallible<F>(callback: F) -> Self
+    where
+        F: Fn(PaintCallbackInfo, &Painter) -> Result<(), String> + Sync + Send + 'static,
+    {
+        let f = Box::new(callback);
+        CallbackFn {
+            f,
+            skip_state_restore: false,
+        }
+    }
+
+    /// Promise that the callback leaves the OpenGL state (framebuffer, program, bindings, blending, …)
+    /// the way it found it, apart from the viewport and scissor rectangle.
+    ///
+    /// Normally the painter has to set up all its state again after each callback,
+    /// which is wasted work for e.g. several callbacks in a row that manage their own state.
+    #[must_use]
+    pub fn skip_state_restore(mut self) -> Self {
+        self.skip_state_restore = true;
+        self
+    }
+}
+
+/// Identifies a screenshot started with [`Painter::request_screenshot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScreenshotToken(u64);
+
+/// Identifies a mesh registered with [`Painter::register_instanced_mesh`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstancedMeshId(u64);
+
+/// The buffers of a mesh registered with [`Painter::register_instanced_mesh`].
+struct InstancedMesh {
+    vbo: glow::Buffer,
+    element_array_buffer: glow::Buffer,
+
+    /// The offset of each instance, uploaded on every [`Painter::paint_instanced_mesh`].
+    instance_buffer: glow::Buffer,
+
+    vao: vao::VertexArrayObject,
+    texture_id: egui::TextureId,
+    index_count: usize,
+}
+
+impl InstancedMesh {
+    unsafe fn destroy(&self, gl: &glow::Context) {
+        self.vao.destroy(gl);
+        gl.delete_buffer(self.vbo);
+        gl.delete_buffer(self.element_array_buffer);
+        gl.delete_buffer(self.instance_buffer);
+    }
+}
+
+/// Sets textures of a [`Painter`] from other threads, e.g. an asset loader, see [`Painter::texture_sender`].
+///
+/// This only sends the texels to the thread of the painter, which uploads them when it next paints,
+/// as the `glow::Context` must only be used on its own thread (it isn't even [`Send`] on the web).
+#[derive(Clone)]
+pub struct TextureSender(mpsc::Sender<(egui::TextureId, ImageDelta)>);
+
+impl TextureSender {
+    /// Set (part of) a texture like [`Painter::set_texture`] does,
+    /// at the start of the next [`Painter::paint_and_update_textures`].
+    ///
+    /// Use [`egui::TextureId::User`] ids that nothing else uses, e.g. with [`egui::TexturesDelta`].
+    /// Returns `false` if the painter is gone.
+    pub fn set_texture(&self, tex_id: egui::TextureId, delta: ImageDelta) -> bool {
+        self.0.send((tex_id, delta)).is_ok()
+    }
+}
+
+/// The receiving end of [`TextureSender`]s.
+///
+/// In mutexes, because the [`Painter`] is [`Sync`], and neither end of a channel is.
+struct TextureQueue {
+    sender: Mutex<mpsc::Sender<(egui::TextureId, ImageDelta)>>,
+    receiver: Mutex<mpsc::Receiver<(egui::TextureId, ImageDelta)>>,
+}
+
+/// How much work the [`Painter`] did for a frame, see [`Painter::last_frame_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PaintStats {
+    /// Draw calls for meshes, including those writing the shapes of rounded clip rects into the stencil buffer.
+    pub draw_calls: usize,
+
+    /// Triangles in those draw calls.
+    pub triangles: usize,
+
+    /// Vertices uploaded for those draw calls.
+    pub vertices: usize,
+
+    /// Full or partial texture uploads since the previous frame, e.g. with [`Painter::set_texture`].
+    pub texture_uploads: usize,
+
+    /// Paint callbacks called.
+    pub callbacks: usize,
+
+    /// Meshes skipped without uploading or drawing them, because they were clipped away entirely.
+    ///
+    /// Consecutive meshes that share a texture and clip rect are merged, and count as one.
+    pub culled_meshes: usize,
+}
+
+enum Screenshot {
+    Pending(PendingScreenshot),
+
+    /// Read synchronously, where we can't do it in the background.
+    Ready(egui::ColorImage),
+}
+
+impl Painter {
+    /// Create painter.
+    ///
+    /// Set `pp_fb_extent` to the framebuffer size to enable `sRGB` support on OpenGL ES and WebGL.
+    ///
+    /// Set `shader_prefix` if you want to turn on shader workaround e.g. `"#define APPLY_BRIGHTENING_GAMMA\n"`
+    /// (see <https://github.com/emilk/egui/issues/794>).
+    ///
+    /// # Errors
+    /// will return `Err` below cases
+    /// * failed to compile shader ([`PainterError::ShaderCompile`] or [`PainterError::ProgramLink`])
+    /// * failed to create postprocess on webgl with `sRGB` support ([`PainterError::PostProcess`])
+    /// * failed to create buffer ([`PainterError::BufferCreation`])
+    pub fn new(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+    ) -> Result<Painter, PainterError> {
+        Self::new_impl(gl, PainterConfig::new(pp_fb_extent, shader_prefix))
+    }
+
+    /// Like [`Self::new`], but always paint via [`Self::intermediate_fbo`], even on desktop OpenGL,
+    /// with a color buffer of the given internal format, e.g. `glow::RGBA16F`.
+    ///
+    /// Colors are blended in linear space there, and then converted to sRGB when copied to the screen.
+    /// Use [`Self::intermediate_color_texture`] to composite egui's linear output yourself instead,
+    /// e.g. in an HDR pipeline. `size` is the initial size of the framebuffer, like `pp_fb_extent`.
+    ///
+    /// # Errors
+    /// Like [`Self::new`], or if the intermediate framebuffer can't be created.
+    pub fn new_with_intermediate_format(
+        gl: Arc<glow::Context>,
+        size: [i32; 2],
+        shader_prefix: &str,
+        internal_format: u32,
+    ) -> Result<Painter, PainterError> {
+        Self::new_impl(
+            gl,
+            PainterConfig {
+                intermediate_format: Some(internal_format),
+                ..PainterConfig::new(Some(size), shader_prefix)
+            },
+        )
+    }
+
+    /// Like [`Self::new`], but use the given shader version instead of detecting it from the context.
+    ///
+    /// An escape hatch for contexts that report a shading language version they don't actually accept.
+    ///
+    /// # Errors
+    /// Like [`Self::new`].
+    pub fn new_with_shader_version(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+        shader_version: ShaderVersion,
+    ) -> Result<Painter, PainterError> {
+        Self::new_impl(
+            gl,
+            PainterConfig {
+                shader_version: Some(shader_version),
+                ..PainterConfig::new(pp_fb_extent, shader_prefix)
+            },
+        )
+    }
+
+    /// Like [`Self::new`], but if the shaders fail to compile or link, try again with a minimal fragment shader
+    /// (`#version 120` on desktop OpenGL, `#version 100` on OpenGL ES and WebGL) without post-processing,
+    /// so that at least something shows up on drivers that reject the normal shaders.
+    ///
+    /// The minimal shader only multiplies the vertex and texture colors, so [`Self::set_dithering`],
+    /// [`Self::set_overdraw_debug`], [`Self::set_color_transform`] and [`Self::set_hdr_scale`] do nothing with it,
+    /// and without sRGB support it blends in gamma space.
+    /// [`Self::is_fallback_shader`] tells if that happened, e.g. to warn the user that colors may look off.
+    ///
+    /// # Errors
+    /// Like [`Self::new`], with the error of the fallback shader if that fails too.
+    pub fn new_with_fallback_shader(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+    ) -> Result<Painter, PainterError> {
+        match Self::new_impl(gl.clone(), PainterConfig::new(pp_fb_extent, shader_prefix)) {
+            Err(err @ (PainterError::ShaderCompile { .. } | PainterError::ProgramLink(_))) => {
+                crate::log::warn!("{}. Falling back to a minimal shader.", err);
+                // `#version 100` is rejected by desktop core profiles, and `#version 120` by OpenGL ES:
+                let shader_version = if gl.version().is_embedded {
+                    ShaderVersion::Es100
+                } else {
+                    ShaderVersion::Gl120
+                };
+                Self::new_impl(
+                    gl,
+                    PainterConfig {
+                        shader_version: Some(shader_version),
+                        fallback_shader: true,
+                        ..PainterConfig::new(None, shader_prefix)
+                    },
+                )
+            }
+            result => result,
+        }
+    }
+
+    /// Like [`Self::new`], but let `patch_shader_source` edit the complete source of each shader before it is compiled,
+    /// e.g. to work around a driver quirk that a `shader_prefix` can't.
+    ///
+    /// `patch_shader_source` is called with the shader type (`glow::VERTEX_SHADER` or `glow::FRAGMENT_SHADER`)
+    /// and the source, including the version header, `shader_prefix` and defines, and returns the source to compile.
+    /// This only applies to the main shaders, not the post-processing ones.
+    ///
+    /// The patched shaders must still use the uniforms `u_screen_size` and `u_sampler`,
+    /// and the vertex attributes `a_pos`, `a_tc` and `a_srgba`.
+    /// The others are optional, but the features that use them won't work without them,
+    /// e.g. [`Self::set_dithering`] without `u_dither`, or [`Self::paint_instanced_mesh`] without `a_offset`.
+    ///
+    /// # Errors
+    /// Like [`Self::new`], and [`PainterError::ProgramLink`] if the patched program lacks
+    /// one of the uniforms or attributes above.
+    pub fn new_with_shader_patch(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+        patch_shader_source: impl Fn(u32, String) -> String + Send + Sync + 'static,
+    ) -> Result<Painter, PainterError> {
+        Self::new_impl(
+            gl,
+            PainterConfig {
+                patch_shader_source: Some(Arc::new(patch_shader_source)),
+                ..PainterConfig::new(pp_fb_extent, shader_prefix)
+            },
+        )
+    }
+
+    /// Like [`Self::new`], but load the shader program from `program_cache` instead of compiling it if possible,
+    /// to cut the startup time on drivers that are slow to compile shaders.
+    ///
+    /// Whenever there is no cached program that fits (e.g. on the first run, or after a driver update),
+    /// the program is compiled and passed to [`ProgramCache::store`].
+    /// Keep those bytes, e.g. in a file, and pass them as [`ProgramCache::cached`] next time.
+    /// Without OpenGL 4.1, `GL_ARB_get_program_binary` or OpenGL ES 3.0 the program is always compiled.
+    /// Programs compiled again later (e.g. by [`Self::set_dithering`]) are not cached.
+    ///
+    /// # Errors
+    /// Like [`Self::new`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_with_program_cache(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+        mut program_cache: ProgramCache<'_>,
+    ) -> Result<Painter, PainterError> {
+        Self::new_impl_with_compile(
+            gl,
+            PainterConfig::new(pp_fb_extent, shader_prefix),
+            move |gl, sources| unsafe {
+                crate::program_cache::load_or_compile(
+                    gl,
+                    &mut program_cache,
+                    sources,
+                    |before_link| compile_program(gl, sources, before_link),
+                )
+            },
+        )
+    }
+
+    fn new_impl(gl: Arc<glow::Context>, config: PainterConfig) -> Result<Painter, PainterError> {
+        Self::new_impl_with_compile(gl, config, |gl, sources| unsafe {
+            compile_program(gl, sources, |_| ())
+        })
+    }
+
+    /// Like [`Self::new_impl`], with `compile` to get the program from the complete sources
+    /// of [`ShaderSources::complete`].
+    fn new_impl_with_compile(
+        gl: Arc<glow::Context>,
+        config: PainterConfig,
+        compile: impl FnOnce(&glow::Context, &[String; 2]) -> Result<glow::Program, PainterError>,
+    ) -> Result<Painter, PainterError> {
+        crate::profile_function!();
+        let PainterConfig {
+            pp_fb_extent,
+            ref shader_prefix,
+            intermediate_format,
+            shader_version,
+            patch_shader_source: _,
+            dithering: _,
+            overdraw_debug: _,
+            fallback_shader: _,
+        } = config;
+        let shader_prefix = shader_prefix.as_str();
+        crate::check_for_gl_error_even_in_release!(&gl, "before Painter::new");
+
+        let max_texture_side = unsafe { gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE) } as usize;
+
+        let supported_extensions = gl.supported_extensions();
+        let max_anisotropy = if supported_extensions.contains("EXT_texture_filter_anisotropic")
+            || supported_extensions.contains("GL_EXT_texture_filter_anisotropic")
+            || supported_extensions.contains("GL_ARB_texture_filter_anisotropic")
+        {
+            Some(unsafe { gl.get_parameter_f32(glow::MAX_TEXTURE_MAX_ANISOTROPY_EXT) })
+        } else {
+            None
+        };
+        crate::log::debug!("Max anisotropy: {:?}.", max_anisotropy);
+
+        let shader_version = shader_version.unwrap_or_else(|| ShaderVersion::get(&gl));
+        let is_webgl_1 = shader_version == ShaderVersion::Es100;
+        let header = shader_version.version();
+        crate::log::debug!("Shader header: {:?}.", header);
+        let srgb_support = supported_extensions.contains("EXT_sRGB");
+        let supports_vao = vao::supports_vao(&gl);
+        crate::log::debug!("VAO supported: {}.", supports_vao);
+
+        let (post_process, srgb_support_define) = match (shader_version, srgb_support) {
+            _ if intermediate_format.is_some() => unsafe {
+                // The post-processing shaders are written for GLSL ES 1.00,
+                // which needs no version header on OpenGL ES, but one on desktop:
+                let header = match shader_version {
+                    ShaderVersion::Es100 | ShaderVersion::Es300 => String::new(),
+                    ShaderVersion::Gl120
+                    | ShaderVersion::Gl140
+                    | ShaderVersion::Gl330Core
+                    | ShaderVersion::Gl410Core => format!(
+                        "{}{}",
+                        shader_version.version(),
+                        shader_version.is_new_shader_interface()
+                    ),
+                };
+                (
+                    Some(
+                        PostProcess::new(
+                            gl.clone(),
+                            &format!("{}{}", header, shader_prefix),
+                            is_webgl_1,
+                            supports_vao,
+                            pp_fb_extent.unwrap_or_default(),
+                            intermediate_format,
+                        )
+                        .map_err(PainterError::PostProcess)?,
+                    ),
+                    "#define SRGB_SUPPORTED",
+                )
+            },
+
+            // WebGL2 support sRGB default
+            (ShaderVersion::Es300, _) | (ShaderVersion::Es100, true) => unsafe {
+                // Add sRGB support marker for fragment shader
+                if let Some(size) = pp_fb_extent {
+                    crate::log::debug!("WebGL with sRGB enabled. Turning on post processing for linear framebuffer blending.");
+                    // install post process to correct sRGB color:
+                    (
+                        Some(
+                            PostProcess::new(
+                                gl.clone(),
+                                shader_prefix,
+                                is_webgl_1,
+                                supports_vao,
+                                size,
+                                None,
+                            )
+                            .map_err(PainterError::PostProcess)?,
+                        ),
+                        "#define SRGB_SUPPORTED",
+                    )
+                } else {
+                    crate::log::debug!("WebGL or OpenGL ES detected but PostProcess disabled because dimension is None");
+                    (None, "")
+                }
+            },
+
+            // WebGL1 without sRGB support disable postprocess and use fallback shader
+            (ShaderVersion::Es100, false) => (None, ""),
+
+            // OpenGL 2.1 or above always support sRGB so add sRGB support marker
+            _ => (None, "#define SRGB_SUPPORTED"),
+        };
+
+        // Texture swizzles are core since OpenGL 3.3, and not available on OpenGL ES 2/3 or WebGL:
+        let font_swizzle_supported = matches!(
+            shader_version,
+            ShaderVersion::Gl140 | ShaderVersion::Gl330Core | ShaderVersion::Gl410Core
+        ) && {
+            let version = gl.version();
+            !version.is_embedded
+                && ((version.major, version.minor) >= (3, 3)
+                    || supported_extensions.contains("GL_ARB_texture_swizzle"))
+        };
+        crate::log::debug!("Texture swizzle supported: {}.", font_swizzle_supported);
+
+        // Sampler objects are core since OpenGL 3.3.
+        // On OpenGL ES and WebGL we keep setting the sampling state on each texture instead:
+        let samplers_supported = {
+            let version = gl.version();
+            !version.is_embedded
+                && ((version.major, version.minor) >= (3, 3)
+                    || supported_extensions.contains("GL_ARB_sampler_objects"))
+        };
+        crate::log::debug!("Sampler objects supported: {}.", samplers_supported);
+
+        // Instanced arrays are core since OpenGL 3.3 and OpenGL ES 3.0 (WebGL2):
+        let instancing_supported = {
+            let version = gl.version();
+            if version.is_embedded {
+                version.major >= 3
+            } else {
+                (version.major, version.minor) >= (3, 3)
+            }
+        };
+        crate::log::debug!("Instanced drawing supported: {}.", instancing_supported);
+
+        // Single-channel textures are core since OpenGL 3.0 and OpenGL ES 3.0 (WebGL2),
+        // so we can store the font atlas as `R8` and expand it in the fragment shader.
+        let r8_font_texture = matches!(
+            shader_version,
+            ShaderVersion::Gl140
+                | ShaderVersion::Gl330Core
+                | ShaderVersion::Gl410Core
+                | ShaderVersion::Es300
+        );
+        let r8_font_texture_define = if r8_font_texture {
+            "#define R8_FONT_TEXTURE"
+        } else {
+            ""
+        };
+
+        // A float intermediate framebuffer can hold colors brighter than white:
+        let hdr_output = is_float_format(intermediate_format);
+        let hdr_output_define = if hdr_output { "#define HDR_OUTPUT" } else { "" };
+
+        let vert_src = format!(
+            "{}\n{}\n{}\n{}",
+            header,
+            shader_prefix,
+            shader_version.is_new_shader_interface(),
+            VERT_SRC
+        );
+        let fragment_header = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            header,
+            shader_prefix,
+            srgb_support_define,
+            r8_font_texture_define,
+            hdr_output_define,
+            shader_version.is_new_shader_interface(),
+        );
+        let shader_sources = ShaderSources {
+            vertex: vert_src,
+            fragment_header,
+        };
+        unsafe {
+            let program = compile(&gl, &shader_sources.complete(&config))?;
+            let locations = UniformLocations::get(&gl, program).and_then(|uniforms| {
+                let attributes = [
+                    required_attrib_location(&gl, program, "a_pos")?,
+                    required_attrib_location(&gl, program, "a_tc")?,
+                    required_attrib_location(&gl, program, "a_srgba")?,
+                ];
+                Ok((uniforms, attributes))
+            });
+            let (
+                UniformLocations {
+                    u_screen_size,
+                    u_sampler,
+                    u_font_r8,
+                    u_straight_alpha,
+                    u_hdr_scale,
+                    u_dither,
+                    u_overdraw,
+                    u_color_transform,
+                },
+                [a_pos_loc, a_tc_loc, a_srgba_loc],
+            ) = match locations {
+                Ok(locations) => locations,
+                Err(err) => {
+                    gl.delete_program(program);
+                    return Err(err);
+                }
+            };
+            let a_offset_loc = gl.get_attrib_location(program, "a_offset");
+
+            let vbo =
+                StreamBuffer::new(&gl, glow::ARRAY_BUFFER).map_err(PainterError::BufferCreation)?;
+
+            let stride = std::mem::size_of::<Vertex>() as i32;
+            let buffer_infos = vec![
+                vao::BufferInfo {
+                    location: a_pos_loc,
+                    vector_size: 2,
+                    data_type: glow::FLOAT,
+                    normalized: false,
+                    stride,
+                    offset: offset_of!(Vertex, pos) as i32,
+                },
+                vao::BufferInfo {
+                    location: a_tc_loc,
+                    vector_size: 2,
+                    data_type: glow::FLOAT,
+                    normalized: false,
+                    stride,
+                    offset: offset_of!(Vertex, uv) as i32,
+                },
+                vao::BufferInfo {
+                    location: a_srgba_loc,
+                    vector_size: 4,
+                    data_type: glow::UNSIGNED_BYTE,
+                    normalized: false,
+                    stride,
+                    offset: offset_of!(Vertex, color) as i32,
+                },
+            ];
+            // The persistent buffer keeps its element array buffer in the VAO:
+            let persistent_buffer = if supports_vao && PersistentMeshBuffer::is_supported(&gl) {
+                PersistentMeshBuffer::new(&gl, buffer_infos.clone())
+                    .map_err(|err| crate::log::warn!("Falling back to streaming meshes: {}", err))
+                    .ok()
+            } else {
+                None
+            };
+            crate::log::debug!(
+                "Persistently mapped mesh buffer: {}",
+                persistent_buffer.is_some()
+            );
+            let vao = crate::vao::VertexArrayObject::new(
+                &gl,
+                vbo.buffer(),
+                buffer_infos.clone(),
+                supports_vao,
+            );
+            let async_screenshots = PendingScreenshot::is_supported(&gl);
+
+            let element_array_buffer = StreamBuffer::new(&gl, glow::ELEMENT_ARRAY_BUFFER)
+                .map_err(PainterError::BufferCreation)?;
+
+            crate::check_for_gl_error_even_in_release!(&gl, "after Painter::new");
+
+            Ok(Painter {
+                gl,
+                max_texture_side,
+                gl_max_texture_side: max_texture_side,
+                max_anisotropy,
+                lod_bias: 0.0,
+                program,
+                u_screen_size,
+                u_sampler,
+                screen_size_in_points: Default::default(),
+                sampler: Default::default(),
+                u_font_r8,
+                u_straight_alpha,
+                u_hdr_scale,
+                hdr_scale_uniform: Default::default(),
+                hdr_scale: 1.0,
+                u_dither,
+                dither_uniform: Default::default(),
+                dithering: false,
+                u_overdraw,
+                overdraw_uniform: Default::default(),
+                overdraw_debug: false,
+                u_color_transform,
+                color_transform_uniform: Default::default(),
+                color_transform: IDENTITY_MATRIX,
+                clip_rounding: ClipRounding::default(),
+                pixel_clips: Vec::new(),
+                damage: None,
+                render_target_size: None,
+                texture_registry: None,
+                render_target: None,
+                viewport_offset: [0, 0],
+                render_scale: 1.0,
+                wireframe: false,
+                texture_queue: None,
+                is_webgl_1,
+                is_embedded: matches!(shader_version, ShaderVersion::Es100 | ShaderVersion::Es300),
+                r8_font_texture,
+                font_swizzle_supported,
+                samplers_supported,
+                samplers: Default::default(),
+                font_swizzle: false,
+                vao,
+                ext_srgb: srgb_support,
+                srgb_shader: !srgb_support_define.is_empty(),
+                post_process,
+                vbo,
+                element_array_buffer,
+                buffer_usage: BufferUsage::default(),
+                framebuffer_srgb: true,
+                blend_func: DEFAULT_BLEND_FUNC,
+                preserve_gl_state: false,
+                preserve_scissor: false,
+                present_intermediate: true,
+                rounded_clip_rects: Vec::new(),
+                persistent_buffer,
+                textures: Default::default(),
+                fallback_texture: None,
+                bound_texture: None,
+                next_native_tex_id: 1 << 32,
+                font_gamma: None,
+                font_texels: Vec::new(),
+                textures_to_destroy: Vec::new(),
+                callback_errors: Vec::new(),
+                frame_stats: PaintStats::default(),
+                last_frame_stats: PaintStats::default(),
+                callback_handlers: HashMap::new(),
+                vertex_attributes: buffer_infos,
+                a_offset_loc,
+                instancing_supported,
+                instanced_meshes: HashMap::new(),
+                next_instanced_mesh_id: 0,
+                async_screenshots,
+                screenshots: HashMap::new(),
+                next_screenshot_token: 0,
+                retain_texture_data: false,
+                retained_textures: HashMap::new(),
+                shader_sources,
+                config,
+                context_lost: false,
+                destroyed: false,
+                suppress_leak_warning: false,
+                destroy_on_drop: false,
+            })
+        }
+    }
+
+    /// Access the shared glow context.
+    pub fn gl(&self) -> &Arc<glow::Context> {
+        &self.gl
+    }
+
+    pub fn max_texture_side(&self) -> usize {
+        self.max_texture_side
+    }
+
+    /// Lower [`Self::max_texture_side`] from what the driver reports as `MAX_TEXTURE_SIZE`,
+    /// for drivers that fail to allocate textures well below that.
+    ///
+    /// Values above `MAX_TEXTURE_SIZE` are clamped to it, and `0` is raised to `1`. Pass the result on to egui
+    /// (`egui::RawInput::max_texture_side`), so that e.g. the font atlas doesn't grow past it.
+    pub fn set_max_texture_side(&mut self, max_texture_side: usize) {
+        self.max_texture_side = clamp_max_texture_side(max_texture_side, self.gl_max_texture_side);
+    }
+
+    /// The highest level accepted by [`Self::set_texture_anisotropy`],
+    /// or `None` if anisotropic filtering is not supported.
+    pub fn max_anisotropy(&self) -> Option<f32> {
+        self.max_anisotropy
+    }
+
+    /// Did [`Self::new_with_fallback_shader`] have to fall back to the minimal shader?
+    pub fn is_fallback_shader(&self) -> bool {
+        self.config.fallback_shader
+    }
+
+    /// The size in pixels of the framebuffer being painted into, e.g. for a paint callback that renders offscreen first
+    /// and wants to match its resolution. `None` outside of [`Self::paint_primitives`].
+    ///
+    /// This is the size of [`Self::intermediate_fbo`] when post-processing, and otherwise the `screen_size_px`
+    /// passed to [`Self::paint_primitives`], which with [`Self::set_viewport_offset`] is only part of the framebuffer.
+    pub fn render_target_size(&self) -> Option<[u32; 2]> {
+        self.render_target_size
+    }
+
+    /// Do we paint into an intermediate framebuffer and copy it to the screen afterwards?
+    ///
+    /// This is the case when created with post-processing (see [`Self::new`]),
+    /// which on OpenGL ES and WebGL is what makes egui blend in linear space. See also [`Self::intermediate_fbo`].
+    pub fn is_post_processing(&self) -> bool {
+        self.post_process.is_some()
+    }
+
+    /// Does the fragment shader work in linear space, with textures that the sampler decodes from sRGB?
+    ///
+    /// If `false` (OpenGL ES and WebGL without post-processing, see [`Self::is_post_processing`]),
+    /// the fragment shader decodes the sRGB texels of non-sRGB textures itself, blends in gamma space,
+    /// and writes gamma-encoded colors. Then textures you pass to [`Self::register_native_texture`]
+    /// should hold sRGB-encoded texels in a linear format such as `RGBA8`, rather than use an sRGB format.
+    pub fn srgb_support(&self) -> bool {
+        self.srgb_shader
+    }
+
+    /// The framebuffer we use as an intermediate render target,
+    /// or `None` if we are painting to the screen framebuffer directly.
+    ///
+    /// This is the framebuffer that is bound when [`egui::Shape::Callback`] is called,
+    /// and is where any callbacks should ultimately render onto.
+    ///
+    /// So if in a [`egui::Shape::Callback`] you need to use an offscreen FBO, you should
+    /// then restore to this afterwards with
+    /// `gl.bind_framebuffer(glow::FRAMEBUFFER, painter.intermediate_fbo());`
+    ///
+    /// With [`Self::set_msaa_samples`], this framebuffer is multisampled, so it can't be read from directly.
+    pub fn intermediate_fbo(&self) -> Option<glow::Framebuffer> {
+        self.post_process.as_ref().map(|pp| pp.fbo())
+    }
+
+    /// Paint [`egui::PaintCallback`]s whose `callback` is a `T` with `handler`,
+    /// for when you want to use your own callback type instead of [`CallbackFn`].
+    ///
+    /// The handler is called just like a [`CallbackFn`] would be, with the viewport already set.
+    /// Registering another handler for the same type replaces the previous one.
+    pub fn register_callback_handler<T: Any + Send + Sync>(
+        &mut self,
+        handler: impl Fn(&T, PaintCallbackInfo, &Painter) + Send + Sync + 'static,
+    ) {
+        self.callback_handlers.insert(
+            TypeId::of::<T>(),
+            Box::new(move |callback, info, painter| {
+                if let Some(callback) = callback.downcast_ref::<T>() {
+                    handler(callback, info, painter);
+                }
+            }),
+        );
+    }
+
+    /// Upload a mesh (in points, like those of egui) once, to paint many copies of it with [`Self::paint_instanced_mesh`],
+    /// e.g. an icon repeated all over a grid.
+    ///
+    /// Free it with [`Self::free_instanced_mesh`]. Registered meshes are forgotten by [`Self::on_context_lost`].
+    pub fn register_instanced_mesh(
+        &mut self,
+        mesh: &Mesh,
+    ) -> Result<InstancedMeshId, PainterError> {
+        crate::profile_function!();
+        self.check_not_destroyed()?;
+        debug_assert!(mesh.is_valid());
+
+        // So that we can paint without the texture, like other meshes:
+        self.fallback_texture();
+
+        let gl = &self.gl;
+        let instanced_mesh = unsafe {
+            let vbo = gl.create_buffer().map_err(PainterError::BufferCreation)?;
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                bytemuck::cast_slice(&mesh.vertices),
+                glow::STATIC_DRAW,
+            );
+
+            let element_array_buffer = gl.create_buffer().map_err(PainterError::BufferCreation)?;
+            let instance_buffer = gl.create_buffer().map_err(PainterError::BufferCreation)?;
+
+            let vao = vao::VertexArrayObject::new(
+                gl,
+                vbo,
+                self.vertex_attributes.clone(),
+                self.vao.is_native(),
+            );
+            // A native VAO records the element array buffer bound while it is bound:
+            vao.bind(gl);
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(element_array_buffer));
+            gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                bytemuck::cast_slice(&mesh.indices),
+                glow::STATIC_DRAW,
+            );
+            vao.unbind(gl);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            check_for_gl_error!(gl, "register_instanced_mesh");
+
+            InstancedMesh {
+                vbo,
+                element_array_buffer,
+                instance_buffer,
+                vao,
+                texture_id: mesh.texture_id,
+                index_count: mesh.indices.len(),
+            }
+        };
+
+        let id = InstancedMeshId(self.next_instanced_mesh_id);
+        self.next_instanced_mesh_id += 1;
+        self.instanced_meshes.insert(id, instanced_mesh);
+        Ok(id)
+    }
+
+    /// Paint a mesh registered with [`Self::register_instanced_mesh`] once at each of `offsets` (in points),
+    /// from a paint callback, clipped to [`egui::PaintCallbackInfo::clip_rect`].
+    ///
+    /// Where instancing is supported (OpenGL 3.3+, OpenGL ES 3.0+ and WebGL2), this is a single draw call.
+    /// Elsewhere, each copy is drawn on its own.
+    ///
+    /// Call this before changing any other state in the callback, as it only sets up what it needs on top of
+    /// the state egui painted with (program, viewport, scissor box and texture).
+    /// Afterwards the program, texture, sampler, vertex array and buffers are unbound,
+    /// and the viewport is the one of the callback again. The scissor box stays set to the clip rect.
+    pub fn paint_instanced_mesh(
+        &self,
+        info: &egui::PaintCallbackInfo,
+        id: InstancedMeshId,
+        offsets: &[egui::Vec2],
+    ) {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        let instanced_mesh = if let Some(instanced_mesh) = self.instanced_meshes.get(&id) {
+            instanced_mesh
+        } else {
+            crate::log::warn!("Failed to find instanced mesh {:?}", id);
+            return;
+        };
+        let a_offset_loc = if let Some(a_offset_loc) = self.a_offset_loc {
+            a_offset_loc
+        } else {
+            crate::log::warn!(
+                "Can't paint instanced meshes without `a_offset` in the vertex shader"
+            );
+            return;
+        };
+        if offsets.is_empty() || self.context_lost {
+            return;
+        }
+        let texture = self.textures.get(&instanced_mesh.texture_id);
+        if texture.is_none() {
+            crate::log::warn!("Failed to find texture {:?}", instanced_mesh.texture_id);
+        }
+
+        let [width_in_pixels, height_in_pixels] = info.screen_size_px;
+        let [x, y] = self.painting_offset();
+        let gl = &self.gl;
+        unsafe {
+            gl.use_program(Some(self.program));
+            gl.viewport(x, y, width_in_pixels as i32, height_in_pixels as i32);
+            self.set_clip_rect(
+                (width_in_pixels, height_in_pixels),
+                info.pixels_per_point,
+                info.clip_rect,
+            );
+
+            gl.active_texture(glow::TEXTURE0);
+            let glow_texture =
+                texture.map_or(self.fallback_texture, |texture| Some(texture.texture));
+            gl.bind_texture(glow::TEXTURE_2D, glow_texture);
+            if self.samplers_supported {
+                // Samplers are only created while painting, so fall back to the texture parameters:
+                let sampler = texture.and_then(|texture| {
+                    let key = SamplerKey::new(&texture.options?, texture.anisotropy);
+                    self.samplers.get(&key).copied()
+                });
+                gl.bind_sampler(0, sampler);
+            }
+            if self.r8_font_texture {
+                let is_r8_font = texture.map_or(false, |texture| texture.is_r8_font);
+                gl.uniform_1_i32(self.u_font_r8.as_ref(), is_r8_font as i32);
+            }
+            let straight_alpha = texture
+                .and_then(|texture| texture.options)
+                .map_or(false, |o| o.straight_alpha);
+            gl.uniform_1_i32(self.u_straight_alpha.as_ref(), straight_alpha as i32);
+
+            instanced_mesh.vao.bind(gl);
+            gl.bind_buffer(
+                glow::ELEMENT_ARRAY_BUFFER,
+                Some(instanced_mesh.element_array_buffer),
+            );
+
+            let count = instanced_mesh.index_count as i32;
+            if self.instancing_supported {
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(instanced_mesh.instance_buffer));
+                gl.buffer_data_u8_slice(
+                    glow::ARRAY_BUFFER,
+                    bytemuck::cast_slice(offsets),
+                    glow::STREAM_DRAW,
+                );
+                gl.enable_vertex_attrib_array(a_offset_loc);
+                gl.vertex_attrib_pointer_f32(a_offset_loc, 2, glow::FLOAT, false, 0, 0);
+                gl.vertex_attrib_divisor(a_offset_loc, 1);
+                gl.draw_elements_instanced(
+                    glow::TRIANGLES,
+                    count,
+                    glow::UNSIGNED_INT,
+                    0,
+                    offsets.len() as i32,
+                );
+                gl.vertex_attrib_divisor(a_offset_loc, 0);
+                gl.disable_vertex_attrib_array(a_offset_loc);
+                gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            } else {
+                for (offset, draw) in offset_steps_without_instancing(offsets) {
+                    gl.vertex_attrib_2_f32(a_offset_loc, offset.x, offset.y);
+                    if draw {
+                        gl.draw_elements(glow::TRIANGLES, count, glow::UNSIGNED_INT, 0);
+                    }
+                }
+            }
+
+            instanced_mesh.vao.unbind(gl);
+            // Emulated VAOs leave it bound:
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+            self.unbind_sampler();
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.use_program(None);
+
+            let viewport_px = info.viewport_in_pixels();
+            gl.viewport(
+                x + viewport_px.left_px,
+                y + viewport_px.from_bottom_px,
+                viewport_px.width_px,
+                viewport_px.height_px,
+            );
+        }
+        check_for_gl_error!(&self.gl, "paint_instanced_mesh");
+    }
+
+    /// Delete the buffers of a mesh registered with [`Self::register_instanced_mesh`].
+    pub fn free_instanced_mesh(&mut self, id: InstancedMeshId) {
+        if let Some(instanced_mesh) = self.instanced_meshes.remove(&id) {
+            if !self.context_lost {
+                unsafe { instanced_mesh.destroy(&self.gl) };
+            }
+        }
+    }
+
+    /// The errors returned by [`CallbackFn::new_fallible`] callbacks during the last call to [`Self::paint_primitives`],
+    /// in the order they happened.
+    pub fn callback_errors(&self) -> &[String] {
+        &self.callback_errors
+    }
+
+    /// How much work the last call to [`Self::paint_primitives`] did, e.g. for a performance overlay.
+    ///
+    /// The texture uploads include those since the frame before, e.g. in [`Self::paint_and_update_textures`].
+    pub fn last_frame_stats(&self) -> PaintStats {
+        self.last_frame_stats
+    }
+
+    /// The texture that [`Self::intermediate_fbo`] renders to (or is resolved into, with [`Self::set_msaa_samples`]),
+    /// or `None` if we are painting to the screen framebuffer directly.
+    ///
+    /// After [`Self::paint_primitives`] it holds egui's output, with colors blended in linear space.
+    /// Its contents are sRGB-encoded, unless you chose a different format with [`Self::new_with_intermediate_format`].
+    /// The texture stays the same when the framebuffer is resized.
+    ///
+    /// To composite it yourself, e.g. as an overlay in your own scene,
+    /// turn off copying it to the screen with [`Self::set_present_intermediate`].
+    pub fn intermediate_color_texture(&self) -> Option<glow::Texture> {
+        self.post_process.as_ref().map(|pp| pp.color_texture())
+    }
+
+    /// Does [`Self::intermediate_fbo`] have a depth buffer, for callbacks that want to do depth testing?
+    ///
+    /// The depth buffer is cleared at the start of every frame. It is sized to the framebuffer.
+    /// If this is `false`, callbacks get whatever depth buffer the framebuffer they paint to has, if any.
+    pub fn intermediate_fbo_has_depth(&self) -> bool {
+        self.post_process
+            .as_ref()
+            .map_or(false, |pp| pp.has_depth_buffer())
+    }
+
+    /// Describe the OpenGL state that painting depends on, e.g. to compare before and after [`Self::paint_primitives`]
+    /// when egui looks wrong on some driver.
+    ///
+    /// This covers what [`Self::set_preserve_gl_state`] saves, plus the bound framebuffer.
+    /// Object bindings are given as raw GL names (zero meaning nothing is bound),
+    /// and enums in hex. The queries are only made when you call this.
+    pub fn debug_gl_state(&self) -> String {
+        unsafe { GlState::describe(&self.gl, self.vao.is_native()) }
+    }
+
+    /// The framebuffer that is currently bound, as far as we can tell.
+    ///
+    /// glow can't turn a raw framebuffer name back into a [`glow::Framebuffer`],
+    /// so this only knows about the default framebuffer and [`Self::intermediate_fbo`].
+    unsafe fn bound_framebuffer(&self) -> Option<glow::Framebuffer> {
+        if self.gl.get_parameter_i32(glow::FRAMEBUFFER_BINDING) == 0 {
+            None
+        } else {
+            self.intermediate_fbo()
+        }
+    }
+
+    unsafe fn prepare_painting(
+        &mut self,
+        [width_in_pixels, height_in_pixels]: [u32; 2],
+        pixels_per_point: f32,
+    ) -> (u32, u32) {
+        self.gl.enable(glow::SCISSOR_TEST);
+        // egui outputs mesh in both winding orders
+        self.gl.disable(glow::CULL_FACE);
+        self.gl.disable(glow::DEPTH_TEST);
+
+        self.gl.color_mask(true, true, true, true);
+
+        self.gl.enable(glow::BLEND);
+        self.gl
+            .blend_equation_separate(glow::FUNC_ADD, glow::FUNC_ADD);
+        let [src_rgb, dst_rgb, src_alpha, dst_alpha] =
+            if self.overdraw_debug && self.u_overdraw.is_some() {
+                [glow::ONE; 4]
+            } else {
+                self.blend_func
+            };
+        self.gl
+            .blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+
+        if !cfg!(target_arch = "wasm32") {
+            if self.framebuffer_srgb {
+                self.gl.enable(glow::FRAMEBUFFER_SRGB);
+            } else {
+                self.gl.disable(glow::FRAMEBUFFER_SRGB);
+            }
+            check_for_gl_error!(&self.gl, "FRAMEBUFFER_SRGB");
+        }
+
+        let width_in_points = width_in_pixels as f32 / pixels_per_point;
+        let height_in_points = height_in_pixels as f32 / pixels_per_point;
+
+        let [x, y] = self.painting_offset();
+        self.gl
+            .viewport(x, y, width_in_pixels as i32, height_in_pixels as i32);
+        self.gl.use_program(Some(self.program));
+
+        if self
+            .screen_size_in_points
+            .update([width_in_points, height_in_points])
+        {
+            self.gl
+                .uniform_2_f32(Some(&self.u_screen_size), width_in_points, height_in_points);
+        }
+        if self.sampler.update(0) {
+            self.gl.uniform_1_i32(Some(&self.u_sampler), 0);
+        }
+        if self.u_dither.is_some() && self.dither_uniform.update(self.dithering) {
+            self.gl
+                .uniform_1_i32(self.u_dither.as_ref(), self.dithering as i32);
+        }
+        if self.u_color_transform.is_some()
+            && self.color_transform_uniform.update(self.color_transform)
+        {
+            self.gl.uniform_matrix_4_f32_slice(
+                self.u_color_transform.as_ref(),
+                false,
+                &self.color_transform,
+            );
+        }
+        if self.u_overdraw.is_some() && self.overdraw_uniform.update(self.overdraw_debug) {
+            self.gl
+                .uniform_1_i32(self.u_overdraw.as_ref(), self.overdraw_debug as i32);
+        }
+        if self.u_hdr_scale.is_some() && self.hdr_scale_uniform.update(self.hdr_scale) {
+            self.gl
+                .uniform_1_f32(self.u_hdr_scale.as_ref(), self.hdr_scale);
+        }
+        self.gl.active_texture(glow::TEXTURE0);
+        self.bound_texture = None;
+
+        self.vao.bind(&self.gl);
+        self.gl.bind_buffer(
+            glow::ELEMENT_ARRAY_BUFFER,
+            Some(self.element_array_buffer.buffer()),
+        );
+
+        check_for_gl_error!(&self.gl, "prepare_painting");
+
+        (width_in_pixels, height_in_pixels)
+    }
+
+    /// Get back to painting after a callback.
+    unsafe fn restore_painting_state(
+        &mut self,
+        screen_fbo: Option<glow::Framebuffer>,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+    ) {
+        if let Some(ref mut post_process) = self.post_process {
+            post_process.bind();
+        } else if screen_fbo.is_some() {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, screen_fbo);
+        }
+        self.prepare_painting(screen_size_px, pixels_per_point);
+    }
+
+    /// You are expected to have cleared the color buffer before calling this.
+    ///
+    /// Textures that can't be uploaded (see [`Self::try_set_texture`]) are logged and skipped.
+    pub fn paint_and_update_textures(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+    ) {
+        crate::profile_function!();
+        self.set_sent_textures();
+        for (id, image_delta) in &textures_delta.set {
+            if let Err(err) = self.try_set_texture(*id, image_delta) {
+                crate::log::error!("Skipping texture {:?}: {}", id, err);
+            }
+        }
+
+        self.paint_primitives(screen_size_px, pixels_per_point, clipped_primitives);
+
+        for &id in &textures_delta.free {
+            self.free_texture(id);
+        }
+
+        self.flush_textures_to_destroy();
+    }
+
+    /// Like [`Self::paint_and_update_textures`], but returns an error instead of panicking
+    /// if the painter has been destroyed.
+    pub fn try_paint_and_update_textures(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+    ) -> Result<(), PainterError> {
+        self.check_not_destroyed()?;
+        self.paint_and_update_textures(
+            screen_size_px,
+            pixels_per_point,
+            clipped_primitives,
+            textures_delta,
+        );
+        Ok(())
+    }
+
+    /// Main entry-point for painting a frame.
+    ///
+    /// You should call `target.clear_color(..)` before
+    /// and `target.finish()` after this.
+    ///
+    /// The following OpenGL features will be set:
+    /// - Scissor test will be enabled
+    /// - Cull face will be disabled
+    /// - Blend will be enabled
+    ///
+    /// The scissor area and blend parameters will be changed.
+    ///
+    /// As well as this, the following objects will be unset:
+    /// - Vertex Buffer
+    /// - Element Buffer
+    /// - Texture (and active texture will be set to 0)
+    /// - Program
+    ///
+    /// Please be mindful of these effects when integrating into your program, and also be mindful
+    /// of the effects your program might have on this code. Look at the source if in doubt.
+    /// Use [`Self::set_preserve_gl_state`] to restore most of the state afterwards.
+    ///
+    /// Nothing is painted if `screen_size_px` is zero in either dimension, e.g. while the window is minimized.
+    pub fn paint_primitives(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+    ) {
+        crate::profile_function!();
+        self.paint_primitives_impl(
+            None,
+            screen_size_px,
+            Some(pixels_per_point),
+            clipped_primitives,
+            None,
+        );
+    }
+
+    /// Like [`Self::paint_primitives`], but with vertex positions and clip rects already in pixels,
+    /// which are used as-is instead of being scaled by `pixels_per_point` (or by [`Self::set_render_scale`]).
+    ///
+    /// This is an escape hatch for non-standard projections, e.g. when painting egui onto a curved surface,
+    /// where you transform the tessellated primitives into the coordinate space of the target yourself.
+    /// Paint callbacks are given a [`egui::PaintCallbackInfo::pixels_per_point`] of `1.0`.
+    pub fn paint_primitives_raw(
+        &mut self,
+        screen_size_px: [u32; 2],
+        clipped_primitives: &[egui::ClippedPrimitive],
+    ) {
+        crate::profile_function!();
+        self.paint_primitives_impl(None, screen_size_px, None, clipped_primitives, None);
+    }
+
+    /// Like [`Self::paint_primitives`], but only repaint `damage`, the rects (in points) that changed since the last frame.
+    ///
+    /// The scissor box is limited to the bounds of all of `damage`, and primitives that would paint nothing inside it
+    /// are skipped entirely. Everything outside the bounds is left as it is, so the framebuffer must still hold
+    /// the previous frame there (e.g. with `EGL_BUFFER_PRESERVED`, or a framebuffer of your own),
+    /// and you should clear only the damage too, see [`clear_damage`].
+    ///
+    /// The intermediate framebuffer used for post-processing (see [`Self::new`]) is repainted every frame,
+    /// so with post-processing `damage` is ignored.
+    pub fn paint_primitives_with_damage(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        damage: &[Rect],
+    ) {
+        crate::profile_function!();
+        let damage = if self.post_process.is_some() {
+            None
+        } else {
+            Some(damage_bounds(damage))
+        };
+        self.paint_primitives_impl(
+            None,
+            screen_size_px,
+            Some(pixels_per_point),
+            clipped_primitives,
+            damage,
+        );
+    }
+
+    /// Like [`Self::paint_primitives`], but paints into `target_fbo` instead of the bound framebuffer,
+    /// e.g. to composite the ui yourself later.
+    ///
+    /// `screen_size_px` is the size of `target_fbo`. With post-processing (see [`Self::new`]),
+    /// the result is presented to `target_fbo` instead of the screen.
+    ///
+    /// Afterwards the framebuffer that was bound before is bound again,
+    /// as long as that was either the default framebuffer or [`Self::intermediate_fbo`].
+    pub fn paint_primitives_to(
+        &mut self,
+        target_fbo: glow::Framebuffer,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+    ) {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        unsafe {
+            let previous_fbo = self.bound_framebuffer();
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(target_fbo));
+            self.paint_primitives_impl(
+                Some(target_fbo),
+                screen_size_px,
+                Some(pixels_per_point),
+                clipped_primitives,
+                None,
+            );
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+        }
+    }
+
+    /// Paint into a texture of `size_px` texels, cleared to transparent first, e.g. to bake a static panel once
+    /// and then show it cheaply every frame.
+    ///
+    /// The painter owns the texture, and paints into the same one on the next call, so copy it if you want to keep it.
+    /// It holds sRGB colors with premultiplied alpha, like the textures of egui itself,
+    /// in an sRGB texture, except on OpenGL ES, where it's an `RGBA8` texture with the sRGB-encoded values.
+    ///
+    /// # Errors
+    /// If the painter has been destroyed, if one of the textures in `textures_delta` can't be uploaded
+    /// (see [`Self::try_set_texture`]), or if the texture to paint into can't be created.
+    pub fn render_to_texture(
+        &mut self,
+        size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+    ) -> Result<glow::Texture, PainterError> {
+        crate::profile_function!();
+        self.check_not_destroyed()?;
+
+        for (id, image_delta) in &textures_delta.set {
+            self.try_set_texture(*id, image_delta)?;
+        }
+
+        let RenderTarget { fbo, texture, .. } = unsafe { self.render_target(size_px)? };
+        unsafe {
+            let previous_fbo = self.bound_framebuffer();
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            clear(&self.gl, size_px, egui::Rgba::TRANSPARENT);
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+        }
+        self.paint_primitives_to(fbo, size_px, pixels_per_point, clipped_primitives);
+
+        for &id in &textures_delta.free {
+            self.free_texture(id);
+        }
+        self.flush_textures_to_destroy();
+
+        Ok(texture)
+    }
+
+    /// The framebuffer for [`Self::render_to_texture`], with a color texture of `size_px`.
+    unsafe fn render_target(&mut self, size_px: [u32; 2]) -> Result<RenderTarget, PainterError> {
+        let gl = &self.gl;
+        let (fbo, texture) = match &self.render_target {
+            Some(render_target) if render_target.size == size_px => {
+                return Ok(*render_target);
+            }
+            Some(render_target) => (render_target.fbo, render_target.texture),
+            None => {
+                let fbo = gl
+                    .create_framebuffer()
+                    .map_err(PainterError::BufferCreation)?;
+                let texture = gl.create_texture().map_err(|err| {
+                    gl.delete_framebuffer(fbo);
+                    PainterError::BufferCreation(err)
+                })?;
+                (fbo, texture)
+            }
+        };
+
+        // On OpenGL ES we paint sRGB-encoded colors (in the shader, or in the post-processing pass),
+        // and an sRGB framebuffer would encode them again, as only desktop GL can turn that off:
+        let format = self.srgba_upload_format(&TextureOptions {
+            linear: self.is_embedded,
+            ..Default::default()
+        });
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            format.internal_format as i32,
+            size_px[0] as i32,
+            size_px[1] as i32,
+            0,
+            format.src_format,
+            format.src_type,
+            None,
+        );
+        apply_texture_options(gl, &TextureOptions::LINEAR, self.lod_bias);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        self.bound_texture = None;
+
+        let previous_fbo = self.bound_framebuffer();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+        gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+        check_for_gl_error!(gl, "render_target");
+
+        let render_target = RenderTarget {
+            fbo,
+            texture,
+            size: size_px,
+        };
+        self.render_target = Some(render_target);
+        Ok(render_target)
+    }
+
+    /// `screen_fbo` is where we paint, or `None` for the bound framebuffer
+    /// (the default framebuffer when presenting the intermediate framebuffer).
+    ///
+    /// `pixels_per_point` is `None` for primitives that are already in pixels.
+    fn paint_primitives_impl(
+        &mut self,
+        screen_fbo: Option<glow::Framebuffer>,
+        screen_size_px: [u32; 2],
+        pixels_per_point: Option<f32>,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        damage: Option<Rect>,
+    ) {
+        self.assert_not_destroyed();
+        let pixels_per_point = scaled_pixels_per_point(pixels_per_point, self.render_scale);
+
+        self.callback_errors.clear();
+        if self.context_lost {
+            return;
+        }
+        if screen_size_px[0] == 0 || screen_size_px[1] == 0 {
+            // E.g. a minimized window. There is nothing to paint,
+            // and the intermediate framebuffer can't be that small.
+            return;
+        }
+
+        let saved_state = if self.preserve_gl_state {
+            Some(unsafe { GlState::save(&self.gl, self.vao.is_native()) })
+        } else {
+            None
+        };
+        let saved_scissor = if self.preserve_scissor && saved_state.is_none() {
+            Some(unsafe { ScissorState::save(&self.gl) })
+        } else {
+            None
+        };
+
+        if let Some(ref mut post_process) = self.post_process {
+            unsafe {
+                if post_process.size() != screen_size_px {
+                    post_process.resize(screen_size_px);
+                }
+                post_process.bind();
+                self.gl.disable(glow::SCISSOR_TEST);
+                self.gl
+                    .viewport(0, 0, screen_size_px[0] as i32, screen_size_px[1] as i32);
+                // use the same clear-color as was set for the screen framebuffer.
+                post_process.clear();
+            }
+        }
+        self.render_target_size = Some(
+            self.post_process
+                .as_ref()
+                .map_or(screen_size_px, |post_process| post_process.size()),
+        );
+        let size_in_pixels = unsafe { self.prepare_painting(screen_size_px, pixels_per_point) };
+        if let Some(persistent_buffer) = &mut self.persistent_buffer {
+            unsafe { persistent_buffer.begin_frame(&self.gl) };
+        }
+
+        let batches = batch_primitives(clipped_primitives, damage);
+        self.damage = damage;
+        let is_callback = |(_, primitive): &(Rect, BatchedPrimitive<'_>)| {
+            matches!(primitive, BatchedPrimitive::Callback(_))
+        };
+
+        // Set after a callback that may have changed the GL state.
+        // We only restore it once we need it, so a callback at the end of the frame costs nothing extra.
+        let mut needs_restore = false;
+
+        // Upload and paint the meshes between callbacks together:
+        for run in batches.split_inclusive(is_callback) {
+            let (meshes, callback) = match run.split_last() {
+                Some(((clip_rect, BatchedPrimitive::Callback(callback)), meshes)) => {
+                    (meshes, Some((*clip_rect, *callback)))
+                }
+                _ => (run, None),
+            };
+
+            if needs_restore {
+                unsafe {
+                    self.restore_painting_state(screen_fbo, screen_size_px, pixels_per_point)
+                };
+                needs_restore = false;
+            }
+
+            self.paint_meshes(size_in_pixels, pixels_per_point, meshes);
+
+            if let Some((clip_rect, callback)) = callback {
+                self.set_clip_rect(size_in_pixels, pixels_per_point, clip_rect);
+
+                if callback.rect.is_positive() {
+                    crate::profile_scope!("callback");
+                    self.frame_stats.callbacks += 1;
+                    let info = egui::PaintCallbackInfo {
+                        viewport: callback.rect,
+                        clip_rect,
+                        pixels_per_point,
+                        screen_size_px,
+                    };
+
+                    let viewport_px = info.viewport_in_pixels();
+                    let [x, y] = self.painting_offset();
+                    unsafe {
+                        self.gl.viewport(
+                            x + viewport_px.left_px,
+                            y + viewport_px.from_bottom_px,
+                            viewport_px.width_px,
+                            viewport_px.height_px,
+                        );
+                    }
+
+                    self.unbind_sampler();
+                    let callback: &(dyn Any + Send + Sync) = &*callback.callback;
+                    needs_restore = true;
+                    if let Some(callback) = callback.downcast_ref::<CallbackFn>() {
+                        if let Err(err) = (callback.f)(info, self) {
+                            crate::log::error!("Paint callback failed: {}", err);
+                            self.callback_errors.push(err);
+                        }
+                        needs_restore = !callback.skip_state_restore;
+                    } else if let Some(handler) = self.callback_handlers.get(&callback.type_id()) {
+                        handler(callback, info, self);
+                    } else {
+                        crate::log::warn!(
+                            "Warning: Unsupported render callback. Expected egui_glow::CallbackFn, or a type registered with Painter::register_callback_handler"
+                        );
+                    }
+
+                    check_for_gl_error!(&self.gl, "callback");
+
+                    if !needs_restore {
+                        // We changed the viewport for the callback ourselves:
+                        unsafe {
+                            self.gl.viewport(
+                                x,
+                                y,
+                                screen_size_px[0] as i32,
+                                screen_size_px[1] as i32,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // The post-processing pass needs our state too:
+        if needs_restore && self.post_process.is_some() {
+            unsafe { self.restore_painting_state(screen_fbo, screen_size_px, pixels_per_point) };
+        }
+        self.unbind_sampler();
+
+        unsafe {
+            if let Some(persistent_buffer) = &mut self.persistent_buffer {
+                persistent_buffer.end_frame(&self.gl);
+            }
+
+            self.vao.unbind(&self.gl);
+            self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+
+            if let Some(ref post_process) = self.post_process {
+                let [x, y] = self.viewport_offset;
+                self.gl
+                    .viewport(x, y, screen_size_px[0] as i32, screen_size_px[1] as i32);
+                post_process.end(screen_fbo, self.present_intermediate);
+            }
+
+            self.gl.disable(glow::SCISSOR_TEST);
+
+            if let Some(saved_state) = saved_state {
+                saved_state.restore(&self.gl);
+            }
+            if let Some(saved_scissor) = saved_scissor {
+                saved_scissor.restore(&self.gl);
+            }
+
+            check_for_gl_error!(&self.gl, "painting");
+        }
+
+        self.damage = None;
+        self.render_target_size = None;
+        self.last_frame_stats = std::mem::take(&mut self.frame_stats);
+    }
+
+    /// Take all pending OpenGL errors, e.g. to assert that [`Self::paint_primitives`] caused none in a test.
+    ///
+    /// Unlike [`crate::check_for_gl_error`], this works in release builds too, and reports every pending error,
+    /// not just the first. Like [`crate::check_for_gl_error_even_in_release`] it is slow, so avoid it in hot paths.
+    pub fn take_gl_error(&self) -> Result<(), crate::GlError> {
+        // Each kind of error is only reported once, but a lost context may keep reporting forever,
+        // so don't loop indefinitely:
+        const MAX_ERRORS: usize = 16;
+
+        let mut codes = Vec::new();
+        while codes.len() < MAX_ERRORS {
+            let code = unsafe { self.gl.get_error() };
+            if code == glow::NO_ERROR {
+                break;
+            }
+            codes.push(code);
+        }
+        if codes.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::GlError { codes })
+        }
+    }
+
+    /// Like [`Self::paint_primitives`], but returns an error instead of panicking
+    /// if the painter has been destroyed.
+    pub fn try_paint_primitives(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+    ) -> Result<(), PainterError> {
+        self.check_not_destroyed()?;
+        self.paint_primitives(screen_size_px, pixels_per_point, clipped_primitives);
+        Ok(())
+    }
+
+    /// Upload all the meshes at once, then paint them one by one.
+    ///
+    /// Any callbacks in `meshes` are ignored.
+    #[inline(never)] // Easier profiling
+    fn paint_meshes(
+        &mut self,
+        size_in_pixels: (u32, u32),
+        pixels_per_point: f32,
+        meshes: &[(Rect, BatchedPrimitive<'_>)],
+    ) {
+        // Skip the meshes that are clipped away entirely before uploading anything:
+        let mut culled_meshes = 0;
+        let meshes: Vec<(Rect, [i32; 4], &Mesh)> = meshes
+            .iter()
+            .filter_map(|(clip_rect, primitive)| match primitive {
+                BatchedPrimitive::Mesh(mesh) => Some((*clip_rect, mesh.as_ref())),
+                BatchedPrimitive::Callback(_) => None,
+            })
+            .filter_map(|(clip_rect, mesh)| {
+                let scissor = self.clip_scissor_box(size_in_pixels, pixels_per_point, clip_rect);
+                if scissor[2] <= 0 || scissor[3] <= 0 {
+                    culled_meshes += 1;
+                    None
+                } else {
+                    Some((clip_rect, scissor, mesh))
+                }
+            })
+            .collect();
+        self.frame_stats.culled_meshes += culled_meshes;
+        if meshes.is_empty() {
+            return;
+        }
+
+        // The post-processing framebuffer has no stencil buffer to clip with.
+        let rounded_clip = |clip_rect: Rect| {
+            if self.post_process.is_some() {
+                return None;
+            }
+            self.rounded_clip_rects
+                .iter()
+                .find(|(rect, _)| *rect == clip_rect)
+                .map(|(_, rounding)| *rounding)
+        };
+
+        // Concatenate the meshes, offsetting the indices so they still point to the right vertices.
+        // The shapes to write into the stencil buffer for rounded clip rects go in between:
+        let mut vertices =
+            Vec::with_capacity(meshes.iter().map(|(_, _, m)| m.vertices.len()).sum());
+        let mut indices = Vec::with_capacity(meshes.iter().map(|(_, _, m)| m.indices.len()).sum());
+        let mut draws = Vec::with_capacity(meshes.len());
+        let mut stenciled_rect = None;
+        for (clip_rect, _, mesh) in &meshes {
+            debug_assert!(mesh.is_valid());
+            let rounded = rounded_clip(*clip_rect);
+            let clip_shape = match rounded {
+                Some(rounding) if stenciled_rect != Some(*clip_rect) => {
+                    stenciled_rect = Some(*clip_rect);
+                    let first_index = indices.len();
+                    add_rounded_rect(&mut vertices, &mut indices, *clip_rect, rounding);
+                    Some((first_index, indices.len() - first_index))
+                }
+                _ => None,
+            };
+            let index_offset = vertices.len() as u32;
+            draws.push((clip_shape, rounded.is_some(), indices.len()));
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend(mesh.indices.iter().map(|index| index + index_offset));
+        }
+
+        let (base_vertex, base_index) = unsafe { self.upload_meshes(&vertices, &indices) };
+
+        // One draw call per mesh. `glMultiDrawElements` would not save anything here:
+        // `batch_primitives` already merged consecutive meshes that share a texture and clip rect,
+        // so there is a state change between every two draws (and `glow` does not expose it anyway).
+        let mut stencil_test = false;
+        // `glPolygonMode` doesn't exist on OpenGL ES and WebGL.
+        // Clip shapes are still filled, so rounded clip rects keep working:
+        let wireframe = self.wireframe && !self.is_embedded;
+        for ((_, scissor, mesh), (clip_shape, rounded, first_index)) in meshes.iter().zip(draws) {
+            self.set_scissor_box(*scissor);
+            unsafe {
+                if let Some((first_index, count)) = clip_shape {
+                    write_clip_shape(&self.gl, base_vertex, base_index + first_index, count);
+                    self.frame_stats.draw_calls += 1;
+                    self.frame_stats.triangles += count / 3;
+                }
+                if rounded != stencil_test {
+                    stencil_test = rounded;
+                    if stencil_test {
+                        self.gl.enable(glow::STENCIL_TEST);
+                    } else {
+                        self.gl.disable(glow::STENCIL_TEST);
+                    }
+                }
+            }
+            self.bind_mesh_texture(mesh.texture_id);
+            unsafe {
+                if wireframe {
+                    self.gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE);
+                }
+                draw_triangles(
+                    &self.gl,
+                    base_vertex,
+                    base_index + first_index,
+                    mesh.indices.len(),
+                );
+                if wireframe {
+                    self.gl.polygon_mode(glow::FRONT_AND_BACK, glow::FILL);
+                }
+            }
+            self.frame_stats.draw_calls += 1;
+            self.frame_stats.triangles += mesh.indices.len() / 3;
+        }
+        self.frame_stats.vertices += vertices.len();
+
+        if stencil_test {
+            unsafe { self.gl.disable(glow::STENCIL_TEST) };
+        }
+
+        check_for_gl_error!(&self.gl, "paint_meshes");
+    }
+
+    /// Upload vertices and indices, and bind the VAO to draw them with.
+    ///
+    /// Returns the base vertex and first index to draw them at.
+    unsafe fn upload_meshes(&mut self, vertices: &[Vertex], indices: &[u32]) -> (i32, usize) {
+        if let Some(persistent_buffer) = &mut self.persistent_buffer {
+            if let Some(offsets) = persistent_buffer.upload(&self.gl, vertices, indices) {
+                return offsets;
+            }
+            // Out of room this frame, so stream them instead:
+            self.vao.bind(&self.gl);
+        }
+
+        let usage = self.buffer_usage.glow_code();
+        self.vbo
+            .upload(&self.gl, bytemuck::cast_slice(vertices), usage);
+        self.element_array_buffer
+            .upload(&self.gl, bytemuck::cast_slice(indices), usage);
+        (0, 0)
+    }
+
+    /// Bind the texture of a mesh, and set the uniforms that depend on it.
+    fn bind_mesh_texture(&mut self, texture_id: egui::TextureId) {
+        let (is_r8_font, straight_alpha, sampler_key) =
+            if let Some(texture) = self.textures.get_mut(&texture_id) {
+                let mut sampler_key = None;
+                unsafe {
+                    if self.bound_texture != Some(texture.texture) {
+                        self.gl
+                            .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                        self.bound_texture = Some(texture.texture);
+                    }
+                    if let Some(options) = texture.options {
+                        if self.samplers_supported {
+                            sampler_key = Some(SamplerKey::new(&options, texture.anisotropy));
+                        } else if texture.applied_options != Some(options) {
+                            apply_texture_options(&self.gl, &options, self.lod_bias);
+                            texture.applied_options = Some(options);
+                        }
+                    }
+                }
+                (
+                    texture.is_r8_font,
+                    texture.options.map_or(false, |o| o.straight_alpha),
+                    sampler_key,
+                )
+            } else {
+                // Probably freed a frame too early.
+                // Paint it untextured rather than leaving a hole:
+                crate::log::warn!("Failed to find texture {:?}", texture_id);
+                let fallback_texture = self.fallback_texture();
+                unsafe {
+                    self.gl
+                        .bind_texture(glow::TEXTURE_2D, Some(fallback_texture));
+                }
+                self.bound_texture = Some(fallback_texture);
+                (false, false, None)
+            };
+
+        if self.samplers_supported {
+            // Native textures and the fallback texture are sampled with their own parameters:
+            let sampler = sampler_key.map(|key| self.sampler(key));
+            unsafe { self.gl.bind_sampler(0, sampler) };
+        }
+
+        unsafe {
+            if self.r8_font_texture {
+                self.gl
+                    .uniform_1_i32(self.u_font_r8.as_ref(), is_r8_font as i32);
+            }
+            self.gl
+                .uniform_1_i32(self.u_straight_alpha.as_ref(), straight_alpha as i32);
+        }
+    }
+
+    /// The cached sampler object for `key`, created on first use.
+    fn sampler(&mut self, key: SamplerKey) -> glow::Sampler {
+        if let Some(sampler) = self.samplers.get(&key) {
+            return *sampler;
+        }
+
+        let sampler = unsafe {
+            let sampler = self.gl.create_sampler().unwrap();
+            self.gl
+                .sampler_parameter_i32(sampler, glow::TEXTURE_MAG_FILTER, key.mag_filter as i32);
+            self.gl
+                .sampler_parameter_i32(sampler, glow::TEXTURE_MIN_FILTER, key.min_filter as i32);
+            self.gl
+                .sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_S, key.wrap_mode as i32);
+            self.gl
+                .sampler_parameter_i32(sampler, glow::TEXTURE_WRAP_T, key.wrap_mode as i32);
+            if let Some(anisotropy) = key.anisotropy {
+                self.gl.sampler_parameter_f32(
+                    sampler,
+                    glow::TEXTURE_MAX_ANISOTROPY_EXT,
+                    f32::from_bits(anisotropy),
+                );
+            }
+            if key.is_mipmapped() && self.lod_bias != 0.0 {
+                self.gl
+                    .sampler_parameter_f32(sampler, glow::TEXTURE_LOD_BIAS, self.lod_bias);
+            }
+            check_for_gl_error!(&self.gl, "sampler");
+            sampler
+        };
+        self.samplers.insert(key, sampler);
+        sampler
+    }
+
+    /// Stop sampling through our sampler objects, e.g. before a callback that binds its own textures.
+    fn unbind_sampler(&self) {
+        if self.samplers_supported {
+            unsafe { self.gl.bind_sampler(0, None) };
+        }
+    }
+
+    /// A 1x1 white texture, created on first use, to paint with when a texture is missing.
+    fn fallback_texture(&mut self) -> glow::Texture {
+        if let Some(texture) = self.fallback_texture {
+            return texture;
+        }
+
+        let texture = unsafe {
+            let texture = self.gl.create_texture().unwrap();
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            apply_texture_options(&self.gl, &TextureOptions::NEAREST, 0.0);
+            self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            self.gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA as _,
+                1,
+                1,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                Some(&[255; 4]),
+            );
+            check_for_gl_error!(&self.gl, "fallback_texture");
+            texture
+        };
+        self.fallback_texture = Some(texture);
+        texture
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Upload (part of) a texture.
+    ///
+    /// Any [`TextureOptions`] previously given to [`Self::set_texture_with_options`] for this texture are kept,
+    /// except for the filter, which is taken from `delta`.
+    /// New textures clamp at the edges.
+    ///
+    /// Panics if the painter has been destroyed, or if the image can't be uploaded
+    /// (e.g. because it is larger than [`Self::max_texture_side`]). See also [`Self::try_set_texture`].
+    pub fn set_texture(&mut self, tex_id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
+        let options = self.delta_options(tex_id, delta);
+        self.set_texture_with_options(tex_id, delta, options);
+    }
+
+    /// The filter of `delta`, with the rest of the options the texture already has.
+    fn delta_options(
+        &self,
+        tex_id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) -> TextureOptions {
+        TextureOptions {
+            magnification: delta.filter,
+            minification: delta.filter,
+            ..self
+                .textures
+                .get(&tex_id)
+                .and_then(|texture| texture.options)
+                .unwrap_or_default()
+        }
+    }
+
+    /// A [`TextureSender`] for setting textures from other threads.
+    ///
+    /// They are set at the start of [`Self::paint_and_update_textures`], or by [`Self::set_sent_textures`].
+    pub fn texture_sender(&mut self) -> TextureSender {
+        let queue = self.texture_queue.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel();
+            TextureQueue {
+                sender: Mutex::new(sender),
+                receiver: Mutex::new(receiver),
+            }
+        });
+        TextureSender(queue.sender.lock().clone())
+    }
+
+    /// Set the textures sent with [`Self::texture_sender`] so far.
+    ///
+    /// Textures that can't be uploaded (see [`Self::try_set_texture`]) are logged and skipped.
+    pub fn set_sent_textures(&mut self) {
+        let sent: Vec<_> = match &self.texture_queue {
+            Some(queue) => queue.receiver.lock().try_iter().collect(),
+            None => return,
+        };
+        for (tex_id, delta) in sent {
+            if let Err(err) = self.try_set_texture(tex_id, &delta) {
+                crate::log::error!("Skipping texture {:?}: {}", tex_id, err);
+            }
+        }
+    }
+
+    /// Like [`Self::set_texture`], but returns an error instead of panicking if the painter has been destroyed,
+    /// e.g. because of teardown ordering during shutdown,
+    /// or if the image can't be uploaded, e.g. because it is empty or larger than [`Self::max_texture_side`].
+    ///
+    /// A rejected image leaves the texture as it was.
+    pub fn try_set_texture(
+        &mut self,
+        tex_id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) -> Result<(), PainterError> {
+        self.check_not_destroyed()?;
+        let options = self.delta_options(tex_id, delta);
+        self.try_set_texture_with_options(tex_id, delta, options)
+    }
+
+    /// Upload (part of) a texture, sampling it with the given options.
+    ///
+    /// The options replace the filter in `delta`, and are remembered for this texture,
+    /// so later calls to [`Self::set_texture`] (e.g. partial updates) won't reset them.
+    pub fn set_texture_with_options(
+        &mut self,
+        tex_id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+        options: TextureOptions,
+    ) {
+        if let Err(err) = self.try_set_texture_with_options(tex_id, delta, options) {
+            panic!("{}", err);
+        }
+    }
+
+    fn try_set_texture_with_options(
+        &mut self,
+        tex_id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+        options: TextureOptions,
+    ) -> Result<(), PainterError> {
+        crate::profile_function!();
+
+        self.assert_not_destroyed();
+
+        let format = match &delta.image {
+            egui::ImageData::Font(_) if self.font_swizzle && self.font_swizzle_supported => {
+                UploadFormat::RG8_FONT
+            }
+            egui::ImageData::Font(_) if self.r8_font_texture => UploadFormat::R8,
+            _ => self.srgba_upload_format(&options),
+        };
+
+        match &delta.image {
+            egui::ImageData::Color(image) => {
+                let data: &[u8] = bytemuck::cast_slice(image.pixels.as_ref());
+
+                self.upload_texels(tex_id, delta.pos, image.size, options, format, data)
+            }
+            egui::ImageData::Font(image) => {
+                let gamma = self.font_gamma.unwrap_or_else(|| {
+                    if self.is_embedded && self.post_process.is_none() {
+                        1.0 / 2.2
+                    } else {
+                        1.0
+                    }
+                });
+                let mut data = std::mem::take(&mut self.font_texels);
+                font_texels(image, gamma, format, &mut data);
+
+                let result =
+                    self.upload_texels(tex_id, delta.pos, image.size, options, format, &data);
+                self.font_texels = data;
+                result
+            }
+        }
+    }
+
+    /// Upload (part of) an HDR texture from half-precision floats, stored as `RGBA16F`.
+    ///
+    /// `data` is linear, premultiplied RGBA with four values per texel,
+    /// each given as the raw bits of an IEEE 754 half-precision float (e.g. `half::f16::to_bits`).
+    /// Values are sampled as-is, so tone-map them to `0..=1` first.
+    /// `pos` is where to put a partial update, or `None` to replace the whole texture.
+    ///
+    /// # Errors
+    /// If the painter has been destroyed, if the image can't be uploaded (see [`Self::try_set_texture`]),
+    /// or on WebGL1, which doesn't support float textures.
+    pub fn set_texture_f16(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        data: &[u16],
+    ) -> Result<(), PainterError> {
+        self.set_texture_raw(
+            tex_id,
+            pos,
+            size,
+            options,
+            UploadFormat::RGBA16F_FROM_F16,
+            bytemuck::cast_slice(data),
+        )
+    }
+
+    /// Upload (part of) an HDR texture from single-precision floats, stored as `RGBA16F`.
+    ///
+    /// Like [`Self::set_texture_f16`], but with `f32` values that OpenGL converts to half floats for us.
+    ///
+    /// # Errors
+    /// Like [`Self::set_texture_f16`].
+    pub fn set_texture_f32(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        data: &[f32],
+    ) -> Result<(), PainterError> {
+        self.set_texture_raw(
+            tex_id,
+            pos,
+            size,
+            options,
+            UploadFormat::RGBA16F_FROM_F32,
+            bytemuck::cast_slice(data),
+        )
+    }
+
+    /// Upload (part of) a texture from 8-bit RGBA texels you already have, without going through [`egui::ImageData`],
+    /// e.g. from your own glyph rasterizer.
+    ///
+    /// Like [`egui::ColorImage`], the texels are premultiplied sRGBA
+    /// (unless [`TextureOptions::linear`] or [`TextureOptions::straight_alpha`] says otherwise),
+    /// with four bytes per texel, row by row from the top.
+    /// `pos` is where to put a partial update, or `None` to replace the whole texture.
+    /// The texels are uploaded straight from `data`, without a copy.
+    ///
+    /// # Errors
+    /// If the painter has been destroyed, or if the image can't be uploaded (see [`Self::try_set_texture`]).
+    pub fn set_texture_rgba8(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        data: &[u8],
+    ) -> Result<(), PainterError> {
+        let format = self.srgba_upload_format(&options);
+        self.set_texture_raw(tex_id, pos, size, options, format, data)
+    }
+
+    /// Upload (part of) a texture from 8-bit BGRA texels, e.g. from a video capture source.
+    ///
+    /// Like [`Self::set_texture_rgba8`], just with the red and blue channels swapped.
+    /// `pos` is where to put a partial update, or `None` to replace the whole texture.
+    ///
+    /// On desktop OpenGL the driver does the swizzle.
+    /// On OpenGL ES and WebGL, where `BGRA` isn't a valid source format for our textures, we swizzle on the CPU.
+    ///
+    /// # Errors
+    /// If the painter has been destroyed, or if the image can't be uploaded (see [`Self::try_set_texture`]).
+    pub fn set_texture_bgra8(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        data: &[u8],
+    ) -> Result<(), PainterError> {
+        let rgba_format = self.srgba_upload_format(&options);
+        if self.is_embedded {
+            let rgba: Vec<u8> = data
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect();
+            self.set_texture_raw(tex_id, pos, size, options, rgba_format, &rgba)
+        } else {
+            let format = UploadFormat {
+                src_format: glow::BGRA,
+                ..rgba_format
+            };
+            self.set_texture_raw(tex_id, pos, size, options, format, data)
+        }
+    }
+
+    /// Upload an image of any size, split into textures of at most [`Self::max_texture_side`] texels per side,
+    /// e.g. a panorama that is too large for [`Self::set_texture`].
+    ///
+    /// Every call allocates new texture ids. Free them with [`Self::free_tiled_texture`].
+    /// With linear filtering, the tiles are not blended into each other at their edges,
+    /// which may show as faint seams when magnified.
+    ///
+    /// # Errors
+    /// If the painter has been destroyed, or if the image can't be uploaded,
+    /// e.g. because it is empty or its pixels don't match its size.
+    /// The tiles uploaded before the error are freed again.
+    pub fn set_tiled_texture(
+        &mut self,
+        image: &egui::ColorImage,
+        options: TextureOptions,
+    ) -> Result<TiledTexture, PainterError> {
+        crate::profile_function!();
+
+        self.check_not_destroyed()?;
+
+        let [width, height] = image.size;
+        if image.pixels.len() != width * height {
+            return Err(PainterError::TextureUpload(format!(
+                "Mismatch between image size and pixel count: {}x{} pixels, but got {}",
+                width,
+                height,
+                image.pixels.len()
+            )));
+        }
+        if width == 0 || height == 0 {
+            return Err(PainterError::TextureUpload(format!(
+                "Got a texture image of size {}x{}. A texture must at least be one texel wide.",
+                width, height
+            )));
+        }
+
+        let format = self.srgba_upload_format(&options);
+        let side = self.max_texture_side;
+        let mut tiles = Vec::new();
+        let mut texels = Vec::with_capacity(side.min(width) * side.min(height));
+        for y in (0..height).step_by(side) {
+            for x in (0..width).step_by(side) {
+                let tile_size = [side.min(width - x), side.min(height - y)];
+                texels.clear();
+                for row in y..y + tile_size[1] {
+                    let start = row * width + x;
+                    texels.extend_from_slice(&image.pixels[start..start + tile_size[0]]);
+                }
+
+                let id = egui::TextureId::User(self.next_native_tex_id);
+                self.next_native_tex_id += 1;
+                let data = bytemuck::cast_slice(&texels);
+                if let Err(err) = self.upload_texels(id, None, tile_size, options, format, data) {
+                    for tile in &tiles {
+                        self.free_texture(tile.id);
+                    }
+                    return Err(err);
+                }
+
+                let min = egui::pos2(x as f32 / width as f32, y as f32 / height as f32);
+                let tile_fraction = egui::vec2(
+                    tile_size[0] as f32 / width as f32,
+                    tile_size[1] as f32 / height as f32,
+                );
+                tiles.push(TextureTile {
+                    id,
+                    rect: Rect::from_min_size(min, tile_fraction),
+                });
+            }
+        }
+
+        Ok(TiledTexture {
+            size: image.size,
+            tiles,
+        })
+    }
+
+    /// Free all the textures of an image uploaded with [`Self::set_tiled_texture`].
+    pub fn free_tiled_texture(&mut self, tiled_texture: &TiledTexture) {
+        for tile in &tiled_texture.tiles {
+            self.free_texture(tile.id);
+        }
+    }
+
+    fn set_texture_raw(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        format: UploadFormat,
+        data: &[u8],
+    ) -> Result<(), PainterError> {
         crate::profile_function!();
+
+        self.check_not_destroyed()?;
+
+        if self.is_webgl_1 && is_float_format(Some(format.internal_format)) {
+            return Err(PainterError::TextureUpload(
+                "Float textures are not supported on WebGL1".to_owned(),
+            ));
+        }
+
+        self.upload_texels(tex_id, pos, size, options, format, data)
+    }
+
+    /// Upload (part of) a texture, creating it if needed,
+    /// and keep a copy if [`Self::set_retain_texture_data`] is on.
+    ///
+    /// Nothing is changed if [`Self::check_texture_upload`] rejects the upload.
+    fn upload_texels(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        size: [usize; 2],
+        options: TextureOptions,
+        format: UploadFormat,
+        data: &[u8],
+    ) -> Result<(), PainterError> {
+        self.check_texture_upload(tex_id, pos, size, format, data)?;
+
+        if self.retain_texture_data {
+            retain_texels(
+                &mut self.retained_textures,
+                tex_id,
+                pos,
+                size,
+                options,
+                format,
+                data,
+            );
+        }
+        if self.context_lost {
+            return Ok(()); // `Self::recreate` uploads the retained copy, if any
+        }
+
+        let whole_size = pos.is_none().then(|| size);
+        self.bind_texture_for_upload(tex_id, options, format, whole_size);
+        self.upload_texture(pos, size, options, format, data);
+        self.frame_stats.texture_uploads += 1;
+        Ok(())
+    }
+
+    /// Check that `data` holds `size` texels of `format`, that the size is supported,
+    /// and that a partial update at `pos` fits within the texture.
+    fn check_texture_upload(
+        &self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        [w, h]: [usize; 2],
+        format: UploadFormat,
+        data: &[u8],
+    ) -> Result<(), PainterError> {
+        if data.len() != w * h * format.bytes_per_texel {
+            return Err(PainterError::TextureUpload(format!(
+                "Mismatch between texture size and texel count: {}x{} texels of {} bytes, but got {} bytes",
+                w,
+                h,
+                format.bytes_per_texel,
+                data.len()
+            )));
+        }
+        if w == 0 || h == 0 {
+            return Err(PainterError::TextureUpload(format!(
+                "Got a texture image of size {}x{}. A texture must at least be one texel wide.",
+                w, h
+            )));
+        }
+        if w > self.max_texture_side || h > self.max_texture_side {
+            return Err(PainterError::TextureUpload(format!(
+                "Got a texture image of size {}x{}, but the maximum supported texture side is only {}",
+                w, h, self.max_texture_side
+            )));
+        }
+        let texture_size = self.textures.get(&tex_id).and_then(|texture| texture.size);
+        if let (Some([x, y]), Some([tex_w, tex_h])) = (pos, texture_size) {
+            if x + w > tex_w || y + h > tex_h {
+                return Err(PainterError::TextureUpload(format!(
+                    "Got a partial texture update of size {}x{} at [{}, {}], but the texture is only {}x{}",
+                    w, h, x, y, tex_w, tex_h
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the texture if needed, remember how it is sampled and stored, and bind it.
+    ///
+    /// `whole_size` is the new size of the texture, or `None` for partial updates.
+    /// The sampler state is only touched on full uploads, or if `options` changed,
+    /// so that e.g. incremental font atlas updates stay cheap.
+    fn bind_texture_for_upload(
+        &mut self,
+        tex_id: egui::TextureId,
+        options: TextureOptions,
+        format: UploadFormat,
+        whole_size: Option<[usize; 2]>,
+    ) {
+        let gl = &self.gl;
+        let texture = self
+            .textures
+            .entry(tex_id)
+            .or_insert_with(|| PainterTexture::native(unsafe { gl.create_texture().unwrap() }));
+        let apply_options = texture.set_upload_options(options, whole_size.is_some());
+        if let Some(size) = whole_size {
+            texture.size = Some(size);
+            texture.internal_format = Some(format.internal_format);
+            texture.is_r8_font = format == UploadFormat::R8;
+            texture.is_swizzled_font = format == UploadFormat::RG8_FONT;
+        }
+        let glow_texture = texture.texture;
+        unsafe {
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(glow_texture));
+            if apply_options {
+                apply_texture_options(&self.gl, &options, self.lod_bias);
+            }
+            if whole_size.is_some() && format == UploadFormat::RG8_FONT {
+                swizzle_font_texture(&self.gl);
+            }
+        }
+    }
+
+    /// The format for sRGBA (or, with [`TextureOptions::linear`], linear RGBA) texels.
+    fn srgba_upload_format(&self, options: &TextureOptions) -> UploadFormat {
+        let (internal_format, src_format) = if options.linear {
+            let format = if self.is_webgl_1 {
+                glow::RGBA
+            } else {
+                glow::RGBA8
+            };
+            (format, glow::RGBA)
+        } else if self.is_webgl_1 {
+            let format = if self.ext_srgb {
+                glow::SRGB_ALPHA
+            } else {
+                glow::RGBA
+            };
+            (format, format)
+        } else {
+            (glow::SRGB8_ALPHA8, glow::RGBA)
+        };
+        UploadFormat {
+            internal_format,
+            src_format,
+            src_type: glow::UNSIGNED_BYTE,
+            bytes_per_texel: 4,
+        }
+    }
+
+    /// Upload to the currently bound texture, after [`Self::check_texture_upload`].
+    fn upload_texture(
+        &mut self,
+        pos: Option<[usize; 2]>,
+        [w, h]: [usize; 2],
+        options: TextureOptions,
+        format: UploadFormat,
+        data: &[u8],
+    ) {
+        unsafe {
+            self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+
+            let level = 0;
+            if let Some([x, y]) = pos {
+                self.gl.tex_sub_image_2d(
+                    glow::TEXTURE_2D,
+                    level,
+                    x as _,
+                    y as _,
+                    w as _,
+                    h as _,
+                    format.src_format,
+                    format.src_type,
+                    glow::PixelUnpackData::Slice(data),
+                );
+                check_for_gl_error!(&self.gl, "tex_sub_image_2d");
+            } else {
+                let border = 0;
+                self.gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    level,
+                    format.internal_format as _,
+                    w as _,
+                    h as _,
+                    border,
+                    format.src_format,
+                    format.src_type,
+                    Some(data),
+                );
+                check_for_gl_error!(&self.gl, "tex_image_2d");
+
+                if options.mipmap_mode.is_some() {
+                    self.gl.generate_mipmap(glow::TEXTURE_2D);
+                    check_for_gl_error!(&self.gl, "generate_mipmap");
+                }
+            }
+        }
+    }
+
+    /// Change how a texture is filtered when magnified and minified, without re-uploading it,
+    /// e.g. to switch an image viewer to [`TextureFilter::Nearest`] when zoomed in past 100%.
+    ///
+    /// The rest of the [`TextureOptions`] (including [`TextureOptions::mipmap_mode`]) are kept,
+    /// and so is the new filter on later calls to [`Self::set_texture`].
+    /// Does nothing if the texture is unknown.
+    pub fn set_texture_filter(&mut self, tex_id: egui::TextureId, filter: TextureFilter) {
         self.assert_not_destroyed();
 
-        if let Some(ref mut post_process) = self.post_process {
+        if let Some(texture) = self.textures.get_mut(&tex_id) {
+            let min_filter = match &mut texture.options {
+                Some(options) => {
+                    let was_applied = texture.applied_options == Some(*options);
+                    options.magnification = filter;
+                    options.minification = filter;
+                    if was_applied {
+                        texture.applied_options = Some(*options);
+                    }
+                    options.glow_min_filter()
+                }
+                None => filter.glow_code(),
+            };
+
             unsafe {
-                post_process.begin(screen_size_px[0] as i32, screen_size_px[1] as i32);
-                post_process.bind();
-                self.gl.disable(glow::SCISSOR_TEST);
                 self.gl
-                    .viewport(0, 0, screen_size_px[0] as i32, screen_size_px[1] as i32);
-                // use the same clear-color as was set for the screen framebuffer.
-                self.gl.clear(glow::COLOR_BUFFER_BIT);
+                    .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                self.gl.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MAG_FILTER,
+                    filter.glow_code() as i32,
+                );
+                self.gl.tex_parameter_i32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MIN_FILTER,
+                    min_filter as i32,
+                );
             }
+            check_for_gl_error!(&self.gl, "set_texture_filter");
         }
-        let size_in_pixels = unsafe { self.prepare_painting(screen_size_px, pixels_per_point) };
-
-        for egui::ClippedPrimitive {
-            clip_rect,
-            primitive,
-        } in clipped_primitives
-        {
-            set_clip_rect(&self.gl, size_in_pixels, pixels_per_point, *clip_rect);
+    }
 
-            match primitive {
-                Primitive::Mesh(mesh) => {
-                    self.paint_mesh(mesh);
-                }
-                Primitive::Callback(callback) => {
-                    if callback.rect.is_positive() {
-                        crate::profile_scope!("callback");
-                        // Transform callback rect to physical pixels:
-                        let rect_min_x = pixels_per_point * callback.rect.min.x;
-                        let rect_min_y = pixels_per_point * callback.rect.min.y;
-                        let rect_max_x = pixels_per_point * callback.rect.max.x;
-                        let rect_max_y = pixels_per_point * callback.rect.max.y;
-
-                        let rect_min_x = rect_min_x.round() as i32;
-                        let rect_min_y = rect_min_y.round() as i32;
-                        let rect_max_x = rect_max_x.round() as i32;
-                        let rect_max_y = rect_max_y.round() as i32;
+    /// Set the anisotropic filtering level of a texture, e.g. for textures seen at grazing angles in 3D callbacks.
+    ///
+    /// `level` is clamped to `1.0..=`[`Self::max_anisotropy`].
+    /// Does nothing if anisotropic filtering is not supported, or if the texture is unknown.
+    pub fn set_texture_anisotropy(&mut self, tex_id: egui::TextureId, level: f32) {
+        self.assert_not_destroyed();
 
-                        unsafe {
-                            self.gl.viewport(
-                                rect_min_x,
-                                size_in_pixels.1 as i32 - rect_max_y,
-                                rect_max_x - rect_min_x,
-                                rect_max_y - rect_min_y,
-                            );
-                        }
+        if let (Some(max_anisotropy), Some(texture)) =
+            (self.max_anisotropy, self.textures.get_mut(&tex_id))
+        {
+            let level = level.clamp(1.0, max_anisotropy);
+            texture.anisotropy = Some(level);
+            unsafe {
+                self.gl
+                    .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                self.gl.tex_parameter_f32(
+                    glow::TEXTURE_2D,
+                    glow::TEXTURE_MAX_ANISOTROPY_EXT,
+                    level,
+                );
+            }
+            check_for_gl_error!(&self.gl, "set_texture_anisotropy");
+        }
+    }
 
-                        let info = egui::PaintCallbackInfo {
-                            viewport: callback.rect,
-                            clip_rect: *clip_rect,
-                            pixels_per_point,
-                            screen_size_px,
-                        };
-
-                        if let Some(callback) = callback.callback.downcast_ref::<CallbackFn>() {
-                            (callback.f)(info, self);
-                        } else {
-                            tracing::warn!("Warning: Unsupported render callback. Expected egui_glow::CallbackFn");
-                        }
+    /// Bias the level of detail that mipmapped textures (see [`TextureOptions::mipmap_mode`]) are sampled at.
+    ///
+    /// Positive values sample smaller mipmaps (blurrier), negative values larger ones (sharper).
+    /// This applies to all current and future mipmapped textures.
+    /// Does nothing on OpenGL ES and WebGL, where `TEXTURE_LOD_BIAS` is unavailable.
+    pub fn set_lod_bias(&mut self, lod_bias: f32) {
+        self.assert_not_destroyed();
 
-                        check_for_gl_error!(&self.gl, "callback");
+        if self.is_embedded {
+            return;
+        }
 
-                        // Restore state:
-                        unsafe {
-                            if let Some(ref mut post_process) = self.post_process {
-                                post_process.bind();
-                            }
-                            self.prepare_painting(screen_size_px, pixels_per_point)
-                        };
-                    }
+        self.lod_bias = lod_bias;
+        for texture in self.textures.values() {
+            if texture.options.map_or(false, |o| o.mipmap_mode.is_some()) {
+                unsafe {
+                    self.gl
+                        .bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                    self.gl
+                        .tex_parameter_f32(glow::TEXTURE_2D, glow::TEXTURE_LOD_BIAS, lod_bias);
+                }
+            }
+        }
+        for (key, sampler) in &self.samplers {
+            if key.is_mipmapped() {
+                unsafe {
+                    self.gl
+                        .sampler_parameter_f32(*sampler, glow::TEXTURE_LOD_BIAS, lod_bias);
                 }
             }
         }
+        check_for_gl_error!(&self.gl, "set_lod_bias");
+    }
 
-        unsafe {
-            self.vao.unbind(&self.gl);
-            self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+    /// Scale the colors egui paints by this factor, e.g. the peak luminance of an HDR display relative to SDR white,
+    /// so that white widgets map into extended sRGB instead of clamping at 1.0.
+    ///
+    /// Only has an effect when painting to a float intermediate framebuffer,
+    /// i.e. when created with [`Self::new_with_intermediate_format`] and `glow::RGBA16F` or `glow::RGBA32F`.
+    /// The colors are only kept above 1.0 if the screen is a float framebuffer too,
+    /// or if you composite [`Self::intermediate_color_texture`] yourself. Defaults to `1.0`.
+    pub fn set_hdr_scale(&mut self, scale: f32) {
+        self.hdr_scale = scale;
+    }
 
-            if let Some(ref post_process) = self.post_process {
-                post_process.end();
-            }
+    /// How clip rectangles are rounded to whole pixels.
+    ///
+    /// Use [`ClipRounding::Outward`] if clip rectangles that abut in points
+    /// leave seams between them at a fractional `pixels_per_point`.
+    ///
+    /// [`egui::PaintCallbackInfo::clip_rect_in_pixels`] always rounds to the nearest pixel,
+    /// so with [`ClipRounding::Outward`] it may be a pixel smaller than the scissor rectangle set for a paint callback.
+    /// Query `glow::SCISSOR_BOX` in the callback if you need the exact one.
+    pub fn set_clip_rounding(&mut self, clip_rounding: ClipRounding) {
+        self.clip_rounding = clip_rounding;
+    }
 
-            self.gl.disable(glow::SCISSOR_TEST);
+    /// Multiply the `pixels_per_point` passed to [`Self::paint_primitives`] by `scale`, e.g. `2.0` to supersample the ui:
+    /// pass a `screen_size_px` that is twice as large too, and scale the result down again in a pass of your own
+    /// (see [`Self::add_post_process_pass`]).
+    ///
+    /// Paint callbacks get the scaled `pixels_per_point`. [`Self::push_pixel_clip`] is not scaled.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale;
+    }
 
-            check_for_gl_error!(&self.gl, "painting");
-        }
+    /// Paint into a rectangle of the framebuffer whose bottom left corner is `offset` pixels
+    /// from the bottom left corner of the framebuffer, instead of at the origin, e.g. for a panel docked inside a game view.
+    ///
+    /// The rectangle has the `screen_size_px` you pass to [`Self::paint_primitives`],
+    /// and the viewport, the clip rects (including [`Self::push_pixel_clip`], which is relative to the rectangle)
+    /// and the viewports of paint callbacks are all moved along.
+    /// [`clear`] clears the whole framebuffer though, so use a paint callback or a scissored clear of your own
+    /// to clear only the rectangle.
+    pub fn set_viewport_offset(&mut self, offset: [i32; 2]) {
+        self.viewport_offset = offset;
     }
 
-    #[inline(never)] // Easier profiling
-    fn paint_mesh(&mut self, mesh: &Mesh) {
-        debug_assert!(mesh.is_valid());
-        if let Some(texture) = self.texture(mesh.texture_id) {
-            unsafe {
-                self.gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
-                self.gl.buffer_data_u8_slice(
-                    glow::ARRAY_BUFFER,
-                    bytemuck::cast_slice(&mesh.vertices),
-                    glow::STREAM_DRAW,
-                );
+    /// Clip everything painted from now on to `rect_in_pixels`, instead of to the `clip_rect` of each primitive,
+    /// until [`Self::pop_pixel_clip`].
+    ///
+    /// `rect_in_pixels` is in physical pixels, from the top left of the screen,
+    /// and is used as-is, without the `pixels_per_point` transform and rounding of [`Self::set_clip_rounding`].
+    /// The most recently pushed rect wins.
+    pub fn push_pixel_clip(&mut self, rect_in_pixels: Rect) {
+        self.pixel_clips.push(rect_in_pixels);
+    }
 
-                self.gl
-                    .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.element_array_buffer));
-                self.gl.buffer_data_u8_slice(
-                    glow::ELEMENT_ARRAY_BUFFER,
-                    bytemuck::cast_slice(&mesh.indices),
-                    glow::STREAM_DRAW,
-                );
+    /// Undo the last [`Self::push_pixel_clip`].
+    pub fn pop_pixel_clip(&mut self) {
+        self.pixel_clips.pop();
+    }
 
-                self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    /// Add a little ordered (Bayer matrix) dithering to the colors egui paints,
+    /// to hide the banding of smooth gradients in 8-bit framebuffers. Off by default.
+    ///
+    /// The perturbation is sized for framebuffers that store sRGB, like the ones egui normally paints to,
+    /// so it is too small to make a difference in dark colors of a framebuffer that stores linear values.
+    ///
+    /// The dithering code is left out of the shader until this is first turned on,
+    /// which compiles the shader program again.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+        if dithering && !self.config.dithering && !self.config.fallback_shader {
+            self.config.dithering = true;
+            if let Err(err) = self.recompile_program() {
+                crate::log::error!("Failed to compile the shader with dithering: {}", err);
+                self.config.dithering = false;
+                self.dithering = false;
             }
+        }
+    }
 
-            unsafe {
-                self.gl.draw_elements(
-                    glow::TRIANGLES,
-                    mesh.indices.len() as i32,
-                    glow::UNSIGNED_INT,
-                    0,
+    /// Compile and link the shader program again, e.g. after a setting changed [`PainterConfig::fragment_defines`].
+    ///
+    /// Keeps the old program on failure. After [`Self::on_context_lost`], [`Self::recreate`] will compile it instead.
+    fn recompile_program(&mut self) -> Result<(), PainterError> {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+        if self.context_lost {
+            return Ok(());
+        }
+        unsafe {
+            let program = self.shader_sources.compile(&self.gl, &self.config)?;
+            let uniforms = match UniformLocations::get(&self.gl, program) {
+                Ok(uniforms) => uniforms,
+                Err(err) => {
+                    self.gl.delete_program(program);
+                    return Err(err);
+                }
+            };
+            self.gl.delete_program(self.program);
+            self.program = program;
+            let UniformLocations {
+                u_screen_size,
+                u_sampler,
+                u_font_r8,
+                u_straight_alpha,
+                u_hdr_scale,
+                u_dither,
+                u_overdraw,
+                u_color_transform,
+            } = uniforms;
+            self.u_screen_size = u_screen_size;
+            self.u_sampler = u_sampler;
+            self.u_font_r8 = u_font_r8;
+            self.u_straight_alpha = u_straight_alpha;
+            self.u_hdr_scale = u_hdr_scale;
+            self.u_dither = u_dither;
+            self.u_overdraw = u_overdraw;
+            self.u_color_transform = u_color_transform;
+        }
+        // The new program starts with every uniform at its default:
+        self.screen_size_in_points = Default::default();
+        self.sampler = Default::default();
+        self.hdr_scale_uniform = Default::default();
+        self.dither_uniform = Default::default();
+        self.overdraw_uniform = Default::default();
+        self.color_transform_uniform = Default::default();
+        Ok(())
+    }
+
+    /// Multiply the color of everything egui paints with a 4x4 matrix (column-major, like OpenGL),
+    /// e.g. for grayscale, high-contrast or color blindness simulation modes. The default is the identity matrix.
+    ///
+    /// The matrix is applied to linear, premultiplied RGBA, after texture sampling and before sRGB encoding.
+    /// Paint callbacks are not affected.
+    ///
+    /// ```
+    /// # fn grayscale(painter: &mut egui_glow::Painter) {
+    /// // Luminance of linear sRGB, in every color channel:
+    /// let [r, g, b] = [0.2126, 0.7152, 0.0722];
+    /// painter.set_color_transform([
+    ///     r, r, r, 0.0, // column 0: the contribution of red
+    ///     g, g, g, 0.0,
+    ///     b, b, b, 0.0,
+    ///     0.0, 0.0, 0.0, 1.0,
+    /// ]);
+    /// # }
+    /// ```
+    pub fn set_color_transform(&mut self, matrix: [f32; 16]) {
+        self.color_transform = matrix;
+    }
+
+    /// Paint every mesh as the same faint gray, added up instead of blended,
+    /// so that the parts of the screen painted many times show up brighter. Off by default.
+    ///
+    /// Use this to find overdraw, e.g. opaque backgrounds painted under everything else.
+    /// Sixteen layers add up to white. Paint callbacks are not affected.
+    ///
+    /// Like [`Self::set_dithering`], the first time this is turned on compiles the shader program again.
+    pub fn set_overdraw_debug(&mut self, overdraw_debug: bool) {
+        self.overdraw_debug = overdraw_debug;
+        if overdraw_debug && !self.config.overdraw_debug && !self.config.fallback_shader {
+            self.config.overdraw_debug = true;
+            if let Err(err) = self.recompile_program() {
+                crate::log::error!(
+                    "Failed to compile the shader for overdraw debugging: {}",
+                    err
                 );
+                self.config.overdraw_debug = false;
+                self.overdraw_debug = false;
             }
-
-            check_for_gl_error!(&self.gl, "paint_mesh");
-        } else {
-            tracing::warn!("Failed to find texture {:?}", mesh.texture_id);
         }
     }
 
-    // ------------------------------------------------------------------------
+    /// Paint the outlines of the triangles of meshes instead of filling them, to see how shapes are tessellated.
+    ///
+    /// Paint callbacks are not affected. Only supported on desktop OpenGL, and ignored on OpenGL ES and WebGL.
+    pub fn set_wireframe(&mut self, wireframe: bool) {
+        self.wireframe = wireframe;
+    }
 
-    pub fn set_texture(&mut self, tex_id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
-        crate::profile_function!();
+    /// Store the font atlas as `RG8` and expand it with `TEXTURE_SWIZZLE_*`,
+    /// so that any shader sampling it as RGBA gets premultiplied white, like for an sRGBA font atlas.
+    ///
+    /// This is for shaders (e.g. in paint callbacks, or patched with [`Self::new_with_shader_patch`])
+    /// that sample the font atlas but don't know about the single-channel `R8` font atlas egui_glow uses by default,
+    /// which the fragment shader expands. The color is stored as 8-bit linear, so faint text edges lose some precision.
+    ///
+    /// Only supported on desktop OpenGL 3.3 or with `GL_ARB_texture_swizzle`, and ignored elsewhere.
+    /// Only updates of the font atlas sent after this call are affected, so call it before the first frame.
+    pub fn set_font_swizzle(&mut self, font_swizzle: bool) {
+        self.font_swizzle = font_swizzle;
+    }
 
-        self.assert_not_destroyed();
+    /// Override the gamma passed to [`egui::epaint::FontImage::srgba_pixels`] when the font atlas is uploaded,
+    /// e.g. to tune how heavy text looks on a particular device.
+    ///
+    /// By default this is `1.0 / 2.2` on OpenGL ES and WebGL without an intermediate framebuffer, and `1.0` elsewhere.
+    /// Lower values make text heavier. Only updates of the font atlas sent after this call are affected,
+    /// so call it before the first frame.
+    pub fn set_font_gamma(&mut self, gamma: f32) {
+        self.font_gamma = Some(gamma);
+    }
 
-        let glow_texture = *self
-            .textures
-            .entry(tex_id)
-            .or_insert_with(|| unsafe { self.gl.create_texture().unwrap() });
-        unsafe {
-            self.gl.bind_texture(glow::TEXTURE_2D, Some(glow_texture));
-        }
+    /// Save the OpenGL state that [`Self::paint_primitives`] changes before painting, and restore it afterwards,
+    /// so that egui can be painted in the middle of another renderer without disturbing it.
+    ///
+    /// This covers the viewport, scissor, blending, color mask, culling (including the cull face and front face),
+    /// depth and stencil tests and `FRAMEBUFFER_SRGB`, as well as the active texture unit and these bindings:
+    /// the texture bound to unit 0, the program, the vertex array, and the array and element array buffers.
+    ///
+    /// Objects other than textures can only be restored if nothing was bound,
+    /// since [`glow`] has no way of referring to them by their raw names,
+    /// so you may still need to rebind your own program, vertex array and buffers afterwards.
+    /// On the web, all of these bindings are left unbound.
+    ///
+    /// Off by default, since querying the state costs a little on every frame.
+    pub fn set_preserve_gl_state(&mut self, preserve_gl_state: bool) {
+        self.preserve_gl_state = preserve_gl_state;
+    }
 
-        match &delta.image {
-            egui::ImageData::Color(image) => {
-                assert_eq!(
-                    image.width() * image.height(),
-                    image.pixels.len(),
-                    "Mismatch between texture size and texel count"
-                );
+    /// Whether to enable `FRAMEBUFFER_SRGB` while painting, so that colors are converted to sRGB
+    /// when written to an sRGB framebuffer. On by default.
+    ///
+    /// Turn this off (which disables `FRAMEBUFFER_SRGB` instead) if you paint into a framebuffer
+    /// that egui's colors should be written to as-is, to avoid converting them twice.
+    /// Has no effect on the web, where `FRAMEBUFFER_SRGB` doesn't exist.
+    pub fn set_framebuffer_srgb(&mut self, framebuffer_srgb: bool) {
+        self.framebuffer_srgb = framebuffer_srgb;
+    }
 
-                let data: &[u8] = bytemuck::cast_slice(image.pixels.as_ref());
+    /// Override the blend function that meshes are painted with, e.g. for additive overlays.
+    ///
+    /// The arguments are passed on to `glBlendFuncSeparate`, e.g. `glow::ONE`.
+    /// The default is `(ONE, ONE_MINUS_SRC_ALPHA, ONE_MINUS_DST_ALPHA, ONE)`,
+    /// since egui outputs premultiplied alpha.
+    pub fn set_blend_func(&mut self, src_rgb: u32, dst_rgb: u32, src_alpha: u32, dst_alpha: u32) {
+        self.blend_func = [src_rgb, dst_rgb, src_alpha, dst_alpha];
+    }
 
-                self.upload_texture_srgb(delta.pos, image.size, delta.filter, data);
-            }
-            egui::ImageData::Font(image) => {
-                assert_eq!(
-                    image.width() * image.height(),
-                    image.pixels.len(),
-                    "Mismatch between texture size and texel count"
-                );
+    /// Leave `SCISSOR_TEST` and the scissor box the way they were before [`Self::paint_primitives`],
+    /// instead of disabling the scissor test afterwards.
+    ///
+    /// This is a cheaper subset of [`Self::set_preserve_gl_state`]. Off by default.
+    pub fn set_preserve_scissor(&mut self, preserve_scissor: bool) {
+        self.preserve_scissor = preserve_scissor;
+    }
 
-                let gamma = if self.is_embedded && self.post_process.is_none() {
-                    1.0 / 2.2
-                } else {
-                    1.0
-                };
-                let data: Vec<u8> = image
-                    .srgba_pixels(gamma)
-                    .flat_map(|a| a.to_array())
-                    .collect();
+    /// Round the corners of some clip rectangles, e.g. those of a window or frame with rounded corners.
+    ///
+    /// Every mesh whose clip rectangle is exactly one of these rectangles is clipped to the rounded rectangle,
+    /// using the stencil buffer, instead of just the rectangle. This lasts until the next call.
+    /// Note that nested widgets, like scroll areas, often clip to a smaller rectangle than their parent,
+    /// which will then still have sharp corners.
+    ///
+    /// The framebuffer you paint to needs a stencil buffer, whose contents in these rectangles will be overwritten.
+    /// The stencil function, operations and write mask are left changed.
+    /// Has no effect when painting via the intermediate framebuffer, since that has no stencil buffer.
+    pub fn set_rounded_clip_rects(&mut self, rounded_clip_rects: Vec<(Rect, egui::Rounding)>) {
+        self.rounded_clip_rects = rounded_clip_rects;
+    }
 
-                self.upload_texture_srgb(delta.pos, image.size, delta.filter, &data);
-            }
+    /// Add a full-screen pass at the end of the post-processing chain, e.g. for a CRT filter or color grading.
+    ///
+    /// `fragment_shader` is GLSL ES 1.00 source without a `#version` line (floats default to `mediump`),
+    /// which reads the output of the previous pass
+    /// from `uniform sampler2D u_sampler` at `varying vec2 v_tc`, and writes to `gl_FragColor`.
+    /// The first pass is the built-in one that converts egui's linear colors to sRGB,
+    /// so the added passes read and write sRGB-encoded colors. The last pass draws to the screen.
+    ///
+    /// # Errors
+    /// If there is no [`Self::intermediate_fbo`] to post-process, or the shader fails to compile.
+    pub fn add_post_process_pass(&mut self, fragment_shader: &str) -> Result<(), String> {
+        let post_process = self
+            .post_process
+            .as_mut()
+            .ok_or_else(|| "Post-processing needs an intermediate framebuffer".to_owned())?;
+        unsafe { post_process.add_pass(fragment_shader) }
+    }
+
+    /// Remove the passes added with [`Self::add_post_process_pass`].
+    pub fn clear_post_process_passes(&mut self) {
+        if let Some(post_process) = &mut self.post_process {
+            unsafe { post_process.clear_passes() };
+        }
+    }
+
+    /// Whether [`Self::paint_primitives`] ends by drawing [`Self::intermediate_color_texture`] to the screen,
+    /// through the post-processing passes. On by default.
+    ///
+    /// Turn this off if you use the texture yourself instead, so egui isn't drawn twice.
+    /// [`Self::add_post_process_pass`] passes are then skipped too.
+    /// Has no effect when painting to the screen directly.
+    pub fn set_present_intermediate(&mut self, present_intermediate: bool) {
+        self.present_intermediate = present_intermediate;
+    }
+
+    /// Paint with multisample anti-aliasing, using this many samples per pixel, clamped to `MAX_SAMPLES`.
+    ///
+    /// This only works when painting via [`Self::intermediate_fbo`], which is then multisampled,
+    /// and resolved before it is copied to the screen. That needs WebGL 2 or OpenGL ES 3.0.
+    /// Otherwise this logs a warning. One sample or less turns MSAA off, which is the default.
+    pub fn set_msaa_samples(&mut self, samples: u32) {
+        let result = match &mut self.post_process {
+            Some(post_process) => unsafe { post_process.set_samples(samples) },
+            None if samples <= 1 => Ok(()),
+            None => Err("MSAA needs an intermediate framebuffer".to_owned()),
         };
+        if let Err(err) = result {
+            crate::log::warn!("Failed to enable MSAA: {}", err);
+        }
     }
 
-    fn upload_texture_srgb(
-        &mut self,
-        pos: Option<[usize; 2]>,
-        [w, h]: [usize; 2],
-        texture_filter: TextureFilter,
-        data: &[u8],
-    ) {
-        assert_eq!(data.len(), w * h * 4);
-        assert!(
-            w >= 1 && h >= 1,
-            "Got a texture image of size {}x{}. A texture must at least be one texel wide.",
-            w,
-            h
-        );
-        assert!(
-            w <= self.max_texture_side && h <= self.max_texture_side,
-            "Got a texture image of size {}x{}, but the maximum supported texture side is only {}",
-            w,
-            h,
-            self.max_texture_side
-        );
+    /// Set the usage hint for the vertex and index buffers that meshes are uploaded to.
+    ///
+    /// Takes effect the next time the buffers are uploaded to. The default is [`BufferUsage::Stream`].
+    pub fn set_buffer_usage(&mut self, usage: BufferUsage) {
+        self.buffer_usage = usage;
+    }
+
+    /// Forget a texture. The GL texture is deleted by [`Self::flush_textures_to_destroy`],
+    /// so that deleting it doesn't stall the draw calls that used it earlier in the frame.
+    pub fn free_texture(&mut self, tex_id: egui::TextureId) {
+        self.retained_textures.remove(&tex_id);
+        if let Some(old_tex) = self.textures.remove(&tex_id) {
+            self.textures_to_destroy.push(old_tex.texture);
+        }
+    }
+
+    /// Read back the contents of a texture, e.g. for screenshot tests.
+    ///
+    /// The texels are returned exactly as stored, i.e. in the same (premultiplied sRGBA) encoding
+    /// as the [`egui::ColorImage`] they were uploaded from, without any sRGB conversion.
+    /// Float textures (e.g. from [`Self::set_texture_f16`]) are linear, so those are converted to sRGBA.
+    ///
+    /// Returns `None` for unknown textures, and for native textures of unknown size
+    /// (see [`Self::texture_size`]).
+    pub fn read_texture(&self, tex_id: egui::TextureId) -> Option<egui::ColorImage> {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        let texture = self.textures.get(&tex_id)?;
+        let [w, h] = texture.size?;
 
         unsafe {
-            self.gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_MAG_FILTER,
-                texture_filter.glow_code() as i32,
-            );
-            self.gl.tex_parameter_i32(
+            let previous_fbo = self.bound_framebuffer();
+            let fbo = self.gl.create_framebuffer().ok()?;
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            self.gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
                 glow::TEXTURE_2D,
-                glow::TEXTURE_MIN_FILTER,
-                texture_filter.glow_code() as i32,
+                Some(texture.texture),
+                0,
             );
 
-            self.gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_WRAP_S,
-                glow::CLAMP_TO_EDGE as i32,
-            );
-            self.gl.tex_parameter_i32(
-                glow::TEXTURE_2D,
-                glow::TEXTURE_WRAP_T,
-                glow::CLAMP_TO_EDGE as i32,
-            );
-            check_for_gl_error!(&self.gl, "tex_parameter");
+            let status = self.gl.check_framebuffer_status(glow::FRAMEBUFFER);
+            let image = if status == glow::FRAMEBUFFER_COMPLETE {
+                // Make sure we get the raw sRGB values back:
+                let framebuffer_srgb =
+                    !cfg!(target_arch = "wasm32") && self.gl.is_enabled(glow::FRAMEBUFFER_SRGB);
+                if framebuffer_srgb {
+                    self.gl.disable(glow::FRAMEBUFFER_SRGB);
+                }
 
-            let (internal_format, src_format) = if self.is_webgl_1 {
-                let format = if self.srgb_support {
-                    glow::SRGB_ALPHA
+                self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+                let mut pixels = if is_float_format(texture.internal_format) {
+                    let mut rgba = vec![0.0_f32; 4 * w * h];
+                    self.gl.read_pixels(
+                        0,
+                        0,
+                        w as _,
+                        h as _,
+                        glow::RGBA,
+                        glow::FLOAT,
+                        glow::PixelPackData::Slice(bytemuck::cast_slice_mut(&mut rgba)),
+                    );
+                    rgba.chunks_exact(4)
+                        .map(|p| egui::Rgba::from_rgba_premultiplied(p[0], p[1], p[2], p[3]).into())
+                        .collect()
                 } else {
-                    glow::RGBA
+                    let mut pixels = vec![Color32::TRANSPARENT; w * h];
+                    self.gl.read_pixels(
+                        0,
+                        0,
+                        w as _,
+                        h as _,
+                        glow::RGBA,
+                        glow::UNSIGNED_BYTE,
+                        glow::PixelPackData::Slice(bytemuck::cast_slice_mut(&mut pixels)),
+                    );
+                    pixels
                 };
-                (format, format)
-            } else {
-                (glow::SRGB8_ALPHA8, glow::RGBA)
-            };
+                check_for_gl_error!(&self.gl, "read_texture");
 
-            self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+                if framebuffer_srgb {
+                    self.gl.enable(glow::FRAMEBUFFER_SRGB);
+                }
 
-            let level = 0;
-            if let Some([x, y]) = pos {
-                self.gl.tex_sub_image_2d(
-                    glow::TEXTURE_2D,
-                    level,
-                    x as _,
-                    y as _,
-                    w as _,
-                    h as _,
-                    src_format,
-                    glow::UNSIGNED_BYTE,
-                    glow::PixelUnpackData::Slice(data),
-                );
-                check_for_gl_error!(&self.gl, "tex_sub_image_2d");
+                if texture.is_r8_font {
+                    // Expand font coverage the same way the fragment shader does:
+                    for pixel in &mut pixels {
+                        let a = pixel.r();
+                        *pixel = Color32::from_rgba_premultiplied(a, a, a, a);
+                    }
+                } else if texture.is_swizzled_font {
+                    // Reading ignores the swizzle, so the coverage is in green:
+                    for pixel in &mut pixels {
+                        let a = pixel.g();
+                        *pixel = Color32::from_rgba_premultiplied(a, a, a, a);
+                    }
+                }
+
+                Some(egui::ColorImage {
+                    size: [w, h],
+                    pixels,
+                })
             } else {
-                let border = 0;
-                self.gl.tex_image_2d(
-                    glow::TEXTURE_2D,
-                    level,
-                    internal_format as _,
-                    w as _,
-                    h as _,
-                    border,
-                    src_format,
-                    glow::UNSIGNED_BYTE,
-                    Some(data),
+                crate::log::warn!(
+                    "Failed to read texture {:?}: framebuffer status 0x{:X}",
+                    tex_id,
+                    status
                 );
-                check_for_gl_error!(&self.gl, "tex_image_2d");
+                None
+            };
+
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+            self.gl.delete_framebuffer(fbo);
+
+            image
+        }
+    }
+
+    /// Read back the frame that was just painted, e.g. for a screenshot.
+    ///
+    /// Call this after [`Self::paint_primitives`], but before swapping buffers,
+    /// after which the contents of the back buffer are undefined.
+    /// This reads from [`Self::intermediate_fbo`] when there is one, otherwise from the bound framebuffer,
+    /// which is normally the default framebuffer.
+    ///
+    /// The framebuffer holds sRGB-encoded colors with premultiplied alpha either way, like [`Color32`],
+    /// so only the rows need flipping to put the top of the screen first.
+    /// The exception is a floating point format chosen with [`Self::new_with_intermediate_format`],
+    /// whose linear colors are converted.
+    /// Without an alpha channel in the framebuffer, all pixels are opaque.
+    ///
+    /// This stalls until the GPU has finished painting.
+    pub fn read_screen_rgba(&self, [width, height]: [u32; 2]) -> egui::ColorImage {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        unsafe {
+            let previous_fbo = self.bind_screen_for_reading();
+            let image = self.read_bound_rgba([width, height], self.is_intermediate_float());
+            if let Some(previous_fbo) = previous_fbo {
+                self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+            }
+            image
+        }
+    }
+
+    /// Read back what [`Self::render_to_texture`] painted last, if anything.
+    #[cfg(feature = "headless")]
+    pub(crate) fn read_render_target(&self) -> Option<egui::ColorImage> {
+        let render_target = self.render_target?;
+        unsafe {
+            let previous_fbo = self.bound_framebuffer();
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(render_target.fbo));
+            let image = self.read_bound_rgba(render_target.size, false);
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+            Some(image)
+        }
+    }
+
+    /// Read the bound framebuffer, which has a floating point color buffer if `float`.
+    unsafe fn read_bound_rgba(&self, [width, height]: [u32; 2], float: bool) -> egui::ColorImage {
+        let (w, h) = (width as usize, height as usize);
+        let mut pixels = vec![Color32::TRANSPARENT; w * h];
+        self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+        if float {
+            let mut rgba = vec![0.0_f32; 4 * w * h];
+            self.gl.read_pixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                glow::RGBA,
+                glow::FLOAT,
+                glow::PixelPackData::Slice(bytemuck::cast_slice_mut(&mut rgba)),
+            );
+            for (pixel, p) in pixels.iter_mut().zip(rgba.chunks_exact(4)) {
+                *pixel = egui::Rgba::from_rgba_premultiplied(p[0], p[1], p[2], p[3]).into();
+            }
+        } else {
+            self.gl.read_pixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(bytemuck::cast_slice_mut(&mut pixels)),
+            );
+        }
+        check_for_gl_error!(&self.gl, "read_screen_rgba");
+        image_from_bottom_up([w, h], pixels)
+    }
+
+    /// Read back the color buffer of [`Self::intermediate_fbo`] (resolved, when using [`Self::set_msaa_samples`]),
+    /// i.e. what was painted before it was converted and copied to the screen, e.g. to test the post-processing path.
+    ///
+    /// Colors were blended in linear space, and are returned sRGB-encoded, with premultiplied alpha,
+    /// like [`Self::read_screen_rgba`].
+    /// Returns `None` if there is no intermediate framebuffer.
+    pub fn capture_intermediate(&self) -> Option<egui::ColorImage> {
+        let size = self.post_process.as_ref()?.size();
+        Some(self.read_screen_rgba(size))
+    }
+
+    /// Like [`Self::read_screen_rgba`], but without waiting for the GPU to finish painting.
+    ///
+    /// The frame is copied into a pixel buffer object in the background.
+    /// Collect it with [`Self::try_get_screenshot`] a frame or two later.
+    /// On WebGL 1, OpenGL ES 2 and OpenGL before 3.2 this falls back to reading the frame right away.
+    pub fn request_screenshot(&mut self, size: [u32; 2]) -> ScreenshotToken {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        // Reading floats back would need a bigger buffer, and converting, so we don't bother:
+        let pending = if self.async_screenshots && !self.is_intermediate_float() {
+            unsafe {
+                let previous_fbo = self.bind_screen_for_reading();
+                let pending = PendingScreenshot::start(&self.gl, size)
+                    .map_err(|err| crate::log::warn!("Failed to start screenshot: {}", err))
+                    .ok();
+                if let Some(previous_fbo) = previous_fbo {
+                    self.gl.bind_framebuffer(glow::FRAMEBUFFER, previous_fbo);
+                }
+                pending
             }
-        }
+        } else {
+            None
+        };
+        let screenshot = match pending {
+            Some(pending) => Screenshot::Pending(pending),
+            None => Screenshot::Ready(self.read_screen_rgba(size)),
+        };
+
+        let token = ScreenshotToken(self.next_screenshot_token);
+        self.next_screenshot_token += 1;
+        self.screenshots.insert(token, screenshot);
+        token
     }
 
-    pub fn free_texture(&mut self, tex_id: egui::TextureId) {
-        if let Some(old_tex) = self.textures.remove(&tex_id) {
-            unsafe { self.gl.delete_texture(old_tex) };
+    /// The screenshot started with [`Self::request_screenshot`], if the GPU is done copying it.
+    ///
+    /// Returns `None` if it isn't ready yet, so call this again later.
+    /// Once the screenshot has been returned, the token is no longer valid.
+    pub fn try_get_screenshot(&mut self, token: ScreenshotToken) -> Option<egui::ColorImage> {
+        let ready = match self.screenshots.get(&token)? {
+            Screenshot::Pending(pending) => unsafe { pending.is_ready(&self.gl) },
+            Screenshot::Ready(_) => true,
+        };
+        if !ready {
+            return None;
         }
+
+        match self.screenshots.remove(&token)? {
+            Screenshot::Pending(pending) => {
+                let size = pending.size();
+                let pixels = unsafe { pending.finish(&self.gl) };
+                Some(image_from_bottom_up(size, pixels))
+            }
+            Screenshot::Ready(image) => Some(image),
+        }
+    }
+
+    /// Does [`Self::intermediate_fbo`] have a floating point format, see [`Self::new_with_intermediate_format`]?
+    fn is_intermediate_float(&self) -> bool {
+        self.post_process
+            .as_ref()
+            .map_or(false, |pp| is_float_format(Some(pp.color_format())))
+    }
+
+    /// Bind the framebuffer we painted to, for reading back from it.
+    ///
+    /// Returns the framebuffer to bind again afterwards, if we had to bind a different one.
+    /// We can only tell which framebuffer to bind back if it's the default one,
+    /// so the binding is left alone unless we have to change it.
+    unsafe fn bind_screen_for_reading(&self) -> Option<Option<glow::Framebuffer>> {
+        let resolved_fbo = self.post_process.as_ref()?.resolved_fbo();
+        let previous_fbo = self.bound_framebuffer();
+        self.gl
+            .bind_framebuffer(glow::FRAMEBUFFER, Some(resolved_fbo));
+        Some(previous_fbo)
     }
 
     /// Get the [`glow::Texture`] bound to a [`egui::TextureId`].
     pub fn texture(&self, texture_id: egui::TextureId) -> Option<glow::Texture> {
-        self.textures.get(&texture_id).copied()
+        self.textures
+            .get(&texture_id)
+            .map(|texture| texture.texture)
+    }
+
+    /// The size of a texture in texels, or `None` if the texture is unknown
+    /// or is a native texture of unknown size.
+    pub fn texture_size(&self, texture_id: egui::TextureId) -> Option<[usize; 2]> {
+        self.textures
+            .get(&texture_id)
+            .and_then(|texture| texture.size)
     }
 
     #[deprecated = "renamed 'texture'"]
@@ -643,51 +4293,542 @@ impl Painter {
         self.texture(texture_id)
     }
 
+    /// Register a texture you created yourself, so egui can paint with it.
+    ///
+    /// The sampler state of the texture is left as is.
+    /// See also [`Self::register_native_texture_with_options`].
+    ///
+    /// Panics if the painter has been destroyed. See also [`Self::try_register_native_texture`].
     #[allow(clippy::needless_pass_by_value)] // False positive
     pub fn register_native_texture(&mut self, native: glow::Texture) -> egui::TextureId {
+        match self.try_register_native_texture(native) {
+            Ok(id) => id,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [`Self::register_native_texture`], but returns an error instead of panicking
+    /// if the painter has been destroyed.
+    #[allow(clippy::needless_pass_by_value)] // False positive
+    pub fn try_register_native_texture(
+        &mut self,
+        native: glow::Texture,
+    ) -> Result<egui::TextureId, PainterError> {
+        self.check_not_destroyed()?;
+        let id = egui::TextureId::User(self.next_native_tex_id);
+        self.next_native_tex_id += 1;
+        self.textures.insert(id, PainterTexture::native(native));
+        Ok(id)
+    }
+
+    /// Track the textures shared with other painters on the same context in `registry`,
+    /// see [`Self::register_shared_texture`]. Set the same registry on all of them before sharing anything.
+    pub fn set_texture_registry(&mut self, registry: Arc<TextureRegistry>) {
+        self.texture_registry = Some(registry);
+    }
+
+    /// Paint with a texture of `other` painter, on the same context, without uploading it again.
+    ///
+    /// The texture is sampled like in `other`, and is only deleted once neither painter uses it anymore.
+    /// Updates of it (e.g. with [`Self::set_texture`]) show up in both painters.
+    ///
+    /// Returns `None` if `other` has no such texture,
+    /// or if the painters don't share a [`Self::set_texture_registry`].
+    pub fn register_shared_texture(
+        &mut self,
+        other: &Painter,
+        tex_id: egui::TextureId,
+    ) -> Option<egui::TextureId> {
         self.assert_not_destroyed();
+
+        let registry = match (&self.texture_registry, &other.texture_registry) {
+            (Some(registry), Some(other_registry)) if Arc::ptr_eq(registry, other_registry) => {
+                registry
+            }
+            _ => {
+                crate::log::warn!(
+                    "Sharing a texture needs the same texture registry in both painters"
+                );
+                return None;
+            }
+        };
+        let texture = other.textures.get(&tex_id)?;
+        registry.share(texture.texture);
+
         let id = egui::TextureId::User(self.next_native_tex_id);
         self.next_native_tex_id += 1;
-        self.textures.insert(id, native);
+        self.textures.insert(
+            id,
+            PainterTexture {
+                applied_options: None,
+                ..*texture
+            },
+        );
+        Some(id)
+    }
+
+    /// Register a texture you created yourself, telling the painter its GL internal format
+    /// (e.g. `glow::RGBA16F`) and size, so that e.g. [`Self::read_texture`] and [`Self::texture_size`] work.
+    ///
+    /// The sampler state of the texture is left as is.
+    #[allow(clippy::needless_pass_by_value)] // False positive
+    pub fn register_native_texture_ex(
+        &mut self,
+        native: glow::Texture,
+        internal_format: u32,
+        size: [usize; 2],
+    ) -> egui::TextureId {
+        let id = self.register_native_texture(native);
+        if let Some(texture) = self.textures.get_mut(&id) {
+            texture.internal_format = Some(internal_format);
+            texture.size = Some(size);
+        }
+        id
+    }
+
+    /// Register a texture you created yourself, and have egui sample it with the given options.
+    ///
+    /// The options are set on the texture the first time it is painted.
+    /// If you use [`TextureOptions::mipmap_mode`], the texture must already have mipmaps.
+    #[allow(clippy::needless_pass_by_value)] // False positive
+    pub fn register_native_texture_with_options(
+        &mut self,
+        native: glow::Texture,
+        options: TextureOptions,
+    ) -> egui::TextureId {
+        let id = self.register_native_texture(native);
+        if let Some(texture) = self.textures.get_mut(&id) {
+            texture.options = Some(options);
+        }
         id
     }
 
+    /// Replace a native texture, e.g. with the next frame of a video.
+    ///
+    /// The old texture is deleted by [`Self::flush_textures_to_destroy`].
+    ///
+    /// The size of the texture will be unknown afterwards.
+    /// Use [`Self::replace_native_texture_with_size`] to keep [`Self::texture_size`] accurate.
     #[allow(clippy::needless_pass_by_value)] // False positive
     pub fn replace_native_texture(&mut self, id: egui::TextureId, replacing: glow::Texture) {
-        if let Some(old_tex) = self.textures.insert(id, replacing) {
-            self.textures_to_destroy.push(old_tex);
+        self.replace_native_texture_impl(id, replacing, None);
+    }
+
+    /// Like [`Self::replace_native_texture`], but also tells the painter the size of the new texture.
+    #[allow(clippy::needless_pass_by_value)] // False positive
+    pub fn replace_native_texture_with_size(
+        &mut self,
+        id: egui::TextureId,
+        replacing: glow::Texture,
+        size: [usize; 2],
+    ) {
+        self.replace_native_texture_impl(id, replacing, Some(size));
+    }
+
+    fn replace_native_texture_impl(
+        &mut self,
+        id: egui::TextureId,
+        replacing: glow::Texture,
+        size: Option<[usize; 2]>,
+    ) {
+        let options = self.textures.get(&id).and_then(|texture| texture.options);
+        if let Some(old_tex) = self.textures.insert(
+            id,
+            PainterTexture {
+                options,
+                size,
+                ..PainterTexture::native(replacing)
+            },
+        ) {
+            self.textures_to_destroy.push(old_tex.texture);
+        }
+    }
+
+    /// Delete the textures freed by [`Self::free_texture`] or replaced by [`Self::replace_native_texture`].
+    ///
+    /// This is called at the end of [`Self::paint_and_update_textures`], after all draw calls of the frame,
+    /// so you only need to call it yourself if you paint with [`Self::paint_primitives`].
+    pub fn flush_textures_to_destroy(&mut self) {
+        for t in std::mem::take(&mut self.textures_to_destroy) {
+            unsafe { self.delete_texture(t) };
+        }
+    }
+
+    /// Delete a texture, unless another painter still uses it (see [`Self::register_shared_texture`]).
+    unsafe fn delete_texture(&self, texture: glow::Texture) {
+        let unused = self
+            .texture_registry
+            .as_ref()
+            .map_or(true, |registry| registry.release(texture));
+        if unused {
+            self.gl.delete_texture(texture);
         }
     }
 
     unsafe fn destroy_gl(&self) {
         self.gl.delete_program(self.program);
         for tex in self.textures.values() {
-            self.gl.delete_texture(*tex);
+            self.delete_texture(tex.texture);
+        }
+        if let Some(texture) = self.fallback_texture {
+            self.gl.delete_texture(texture);
+        }
+        for sampler in self.samplers.values() {
+            self.gl.delete_sampler(*sampler);
+        }
+        for instanced_mesh in self.instanced_meshes.values() {
+            instanced_mesh.destroy(&self.gl);
+        }
+        self.vbo.destroy(&self.gl);
+        self.element_array_buffer.destroy(&self.gl);
+        self.vao.destroy(&self.gl);
+        if let Some(persistent_buffer) = &self.persistent_buffer {
+            persistent_buffer.destroy(&self.gl);
         }
-        self.gl.delete_buffer(self.vbo);
-        self.gl.delete_buffer(self.element_array_buffer);
         for t in &self.textures_to_destroy {
-            self.gl.delete_texture(*t);
+            self.delete_texture(*t);
+        }
+        for screenshot in self.screenshots.values() {
+            if let Screenshot::Pending(pending) = screenshot {
+                pending.destroy(&self.gl);
+            }
+        }
+        if let Some(render_target) = &self.render_target {
+            self.gl.delete_framebuffer(render_target.fbo);
+            self.gl.delete_texture(render_target.texture);
         }
     }
 
     /// This function must be called before [`Painter`] is dropped, as [`Painter`] has some OpenGL objects
     /// that should be deleted.
+    ///
+    /// Calling it again does nothing. See also [`Self::set_destroy_on_drop`].
     pub fn destroy(&mut self) {
-        if !self.destroyed {
+        if !self.destroyed && !self.context_lost {
+            unsafe {
+                self.destroy_gl();
+                if let Some(ref post_process) = self.post_process {
+                    post_process.destroy();
+                }
+            }
+        }
+        self.destroyed = true;
+    }
+
+    /// Don't warn about leaked resources when the [`Painter`] is dropped without calling [`Self::destroy`].
+    ///
+    /// Use this when you know the OpenGL objects died with the context,
+    /// e.g. in a test harness that tears down the context first, where calling [`Self::destroy`] would be unsafe.
+    /// The warning is on by default.
+    pub fn set_suppress_leak_warning(&mut self, suppress: bool) {
+        self.suppress_leak_warning = suppress;
+    }
+
+    /// Call [`Self::destroy`] automatically when the [`Painter`] is dropped,
+    /// if it then holds the only reference to its [`glow::Context`].
+    ///
+    /// Use this when you hand the context over to the painter, and keep it current for as long as the painter lives.
+    /// While the context is shared, we can't know that it is still alive when we are dropped,
+    /// so you still need to call [`Self::destroy`] yourself, or get the leak warning.
+    /// Off by default.
+    pub fn set_destroy_on_drop(&mut self, destroy_on_drop: bool) {
+        self.destroy_on_drop = destroy_on_drop;
+    }
+
+    /// We hold the only references to [`Self::gl`], counting the one of the post-processing pass.
+    fn owns_context(&self) -> bool {
+        Arc::strong_count(&self.gl) == 1 + self.post_process.is_some() as usize
+            && Arc::weak_count(&self.gl) == 0
+    }
+
+    /// Drop the [`Painter`] without deleting its OpenGL objects, and without the leak warning.
+    ///
+    /// Like [`Self::set_suppress_leak_warning`], this is for when the objects already died with the context.
+    pub fn forget(mut self) {
+        self.destroyed = true;
+    }
+
+    /// Forget all OpenGL objects, without deleting them, after the context has been lost,
+    /// e.g. on a `webglcontextlost` event on the web.
+    ///
+    /// Painting and texture uploads do nothing until [`Self::recreate`] is called with the restored context.
+    pub fn on_context_lost(&mut self) {
+        self.assert_not_destroyed();
+
+        crate::log::debug!("Forgetting all OpenGL objects after the context was lost");
+        self.context_lost = true;
+        // The sampling options may have changed since the upload, e.g. with `set_texture_filter`:
+        for (tex_id, texture) in &self.textures {
+            if let (Some(retained), Some(options)) =
+                (self.retained_textures.get_mut(tex_id), texture.options)
+            {
+                retained.options = options;
+            }
+        }
+        self.textures.clear();
+        self.fallback_texture = None;
+        self.bound_texture = None;
+        self.samplers.clear();
+        self.instanced_meshes.clear();
+        self.textures_to_destroy.clear();
+        self.render_target = None;
+        self.screenshots
+            .retain(|_, screenshot| matches!(screenshot, Screenshot::Ready(_)));
+    }
+
+    /// Create all OpenGL objects again in `gl`, e.g. after [`Self::on_context_lost`] and a `webglcontextrestored` event.
+    ///
+    /// The settings made with the `set_*` methods and the registered callback handlers are kept,
+    /// but post-processing passes and MSAA have to be set up again.
+    /// With [`Self::set_retain_texture_data`], the textures are uploaded again from their retained copies.
+    /// Otherwise all textures are gone, including egui's own, which egui only sends again when they change,
+    /// so set them again with e.g. [`Self::set_texture`]. Native textures always have to be registered again.
+    ///
+    /// Without [`Self::on_context_lost`] first, the old objects are deleted before the new ones are created,
+    /// so the old context must still be current, e.g. when `gl` is the same context.
+    ///
+    /// # Errors
+    /// Like [`Self::new`], in which case the painter stays unusable.
+    pub fn recreate(&mut self, gl: Arc<glow::Context>) -> Result<(), PainterError> {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        if !self.context_lost {
+            // The old objects are still alive, so delete them instead of leaking them:
             unsafe {
                 self.destroy_gl();
                 if let Some(ref post_process) = self.post_process {
                     post_process.destroy();
                 }
             }
-            self.destroyed = true;
+            self.on_context_lost();
+        }
+
+        let config = PainterConfig {
+            pp_fb_extent: match &self.post_process {
+                Some(post_process) => {
+                    let [width, height] = post_process.size();
+                    Some([width as i32, height as i32])
+                }
+                None => self.config.pp_fb_extent,
+            },
+            shader_prefix: self.config.shader_prefix.clone(),
+            intermediate_format: self.config.intermediate_format,
+            shader_version: self.config.shader_version,
+            patch_shader_source: self.config.patch_shader_source.clone(),
+            dithering: self.config.dithering,
+            overdraw_debug: self.config.overdraw_debug,
+            fallback_shader: self.config.fallback_shader,
+        };
+        let fresh = Self::new_impl(gl, config)?;
+        let mut old = std::mem::replace(self, fresh);
+        old.destroyed = true; // its objects are deleted or in the lost context
+
+        self.lod_bias = old.lod_bias;
+        self.font_gamma = old.font_gamma;
+        self.hdr_scale = old.hdr_scale;
+        self.dithering = old.dithering;
+        self.overdraw_debug = old.overdraw_debug;
+        self.color_transform = old.color_transform;
+        self.clip_rounding = old.clip_rounding;
+        self.pixel_clips = std::mem::take(&mut old.pixel_clips);
+        self.texture_registry = old.texture_registry.take();
+        self.viewport_offset = old.viewport_offset;
+        self.render_scale = old.render_scale;
+        self.max_texture_side =
+            clamp_max_texture_side(old.max_texture_side, self.gl_max_texture_side);
+        self.wireframe = old.wireframe;
+        self.font_swizzle = old.font_swizzle;
+        self.suppress_leak_warning = old.suppress_leak_warning;
+        self.destroy_on_drop = old.destroy_on_drop;
+        self.texture_queue = old.texture_queue.take();
+        self.buffer_usage = old.buffer_usage;
+        self.framebuffer_srgb = old.framebuffer_srgb;
+        self.blend_func = old.blend_func;
+        self.preserve_gl_state = old.preserve_gl_state;
+        self.preserve_scissor = old.preserve_scissor;
+        self.present_intermediate = old.present_intermediate;
+        self.rounded_clip_rects = std::mem::take(&mut old.rounded_clip_rects);
+        self.callback_handlers = std::mem::take(&mut old.callback_handlers);
+        self.screenshots = std::mem::take(&mut old.screenshots);
+        self.next_screenshot_token = old.next_screenshot_token;
+        self.next_native_tex_id = old.next_native_tex_id;
+        self.next_instanced_mesh_id = old.next_instanced_mesh_id;
+        self.retain_texture_data = old.retain_texture_data;
+        self.retained_textures = std::mem::take(&mut old.retained_textures);
+        self.reupload_all_textures();
+        Ok(())
+    }
+
+    /// Keep a CPU-side copy of every texture uploaded with [`Self::set_texture`] and friends,
+    /// so that [`Self::reupload_all_textures`] and [`Self::recreate`] can upload them again, e.g. after the context was lost.
+    ///
+    /// Off by default, since it doubles the memory used for textures. Turning it off drops the copies.
+    /// Only textures uploaded after turning it on are retained, so do so before the first frame.
+    /// Native textures (see [`Self::register_native_texture`]) are not retained, and are for the caller to rebuild.
+    pub fn set_retain_texture_data(&mut self, retain: bool) {
+        self.retain_texture_data = retain;
+        if !retain {
+            self.retained_textures = HashMap::new();
+        }
+    }
+
+    /// Upload every texture retained with [`Self::set_retain_texture_data`] again, replacing their current contents.
+    ///
+    /// [`Self::recreate`] already does this.
+    pub fn reupload_all_textures(&mut self) {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+        if self.context_lost {
+            return;
+        }
+
+        let retained_textures = std::mem::take(&mut self.retained_textures);
+        for (tex_id, retained) in &retained_textures {
+            let options = self
+                .textures
+                .get(tex_id)
+                .and_then(|texture| texture.options)
+                .unwrap_or(retained.options);
+            self.bind_texture_for_upload(*tex_id, options, retained.format, Some(retained.size));
+            self.upload_texture(
+                None,
+                retained.size,
+                options,
+                retained.format,
+                &retained.data,
+            );
+        }
+        self.retained_textures = retained_textures;
+    }
+
+    /// Set the scissor box for a primitive, see [`Self::clip_scissor_box`].
+    fn set_clip_rect(&self, size_in_pixels: (u32, u32), pixels_per_point: f32, clip_rect: Rect) {
+        self.set_scissor_box(self.clip_scissor_box(size_in_pixels, pixels_per_point, clip_rect));
+    }
+
+    /// The scissor box for a primitive, unless overridden by [`Self::push_pixel_clip`],
+    /// and limited to the damage, if any.
+    fn clip_scissor_box(
+        &self,
+        size_in_pixels: (u32, u32),
+        pixels_per_point: f32,
+        clip_rect: Rect,
+    ) -> [i32; 4] {
+        let mut scissor = match self.pixel_clips.last() {
+            Some(pixel_clip) => scissor_box(size_in_pixels, 1.0, *pixel_clip, ClipRounding::Round),
+            None => scissor_box(
+                size_in_pixels,
+                pixels_per_point,
+                clip_rect,
+                self.clip_rounding,
+            ),
+        };
+        if let Some(damage) = self.damage {
+            let damage = scissor_box(
+                size_in_pixels,
+                pixels_per_point,
+                damage,
+                ClipRounding::Outward,
+            );
+            scissor = intersect_scissor_boxes(scissor, damage);
+        }
+        scissor
+    }
+
+    /// Set a scissor box from [`scissor_box`], moved by [`Self::set_viewport_offset`].
+    fn set_scissor_box(&self, [x, y, width, height]: [i32; 4]) {
+        let [offset_x, offset_y] = self.painting_offset();
+        unsafe {
+            self.gl.scissor(offset_x + x, offset_y + y, width, height);
+        }
+    }
+
+    /// Where in the bound framebuffer we paint, see [`Self::set_viewport_offset`].
+    ///
+    /// The intermediate framebuffer is just as large as what we paint, so the offset only applies when presenting it.
+    fn painting_offset(&self) -> [i32; 2] {
+        if self.post_process.is_some() {
+            [0, 0]
+        } else {
+            self.viewport_offset
         }
     }
 
     fn assert_not_destroyed(&self) {
         assert!(!self.destroyed, "the egui glow has already been destroyed!");
     }
+
+    fn check_not_destroyed(&self) -> Result<(), PainterError> {
+        if self.destroyed {
+            Err(PainterError::Destroyed)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Like [`clear`], but convert `clear_color` to sRGB the same way as the colors [`Painter::paint_primitives`] paints,
+/// so that the background matches e.g. a panel of the same color.
+///
+/// On desktop OpenGL, this enables `FRAMEBUFFER_SRGB` during the clear and restores it afterwards,
+/// so an sRGB framebuffer gets the encoded color, like it gets encoded mesh colors (see [`Painter::set_framebuffer_srgb`]).
+/// On OpenGL ES and WebGL the color is encoded before clearing instead,
+/// since there egui encodes mesh colors itself when painting without post-processing.
+///
+/// Use this when painting without post-processing. With post-processing (see [`Painter::new`]),
+/// the intermediate framebuffer is cleared with the clear color set by [`clear`],
+/// and it is what ends up on the screen, so keep using [`clear`] then.
+pub fn clear_srgb(gl: &glow::Context, screen_size_in_pixels: [u32; 2], clear_color: egui::Rgba) {
+    crate::profile_function!();
+    if cfg!(target_arch = "wasm32") || gl.version().is_embedded {
+        let [r, g, b, a] = Color32::from(clear_color).to_array();
+        let encoded = egui::Rgba::from_rgba_premultiplied(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        );
+        clear(gl, screen_size_in_pixels, encoded);
+        return;
+    }
+    unsafe {
+        let framebuffer_srgb = gl.is_enabled(glow::FRAMEBUFFER_SRGB);
+        gl.enable(glow::FRAMEBUFFER_SRGB);
+        clear(gl, screen_size_in_pixels, clear_color);
+        if !framebuffer_srgb {
+            gl.disable(glow::FRAMEBUFFER_SRGB);
+        }
+    }
+}
+
+/// Like [`clear`], but only clear the bounds of `damage` (in points), for [`Painter::paint_primitives_with_damage`].
+pub fn clear_damage(
+    gl: &glow::Context,
+    screen_size_in_pixels: [u32; 2],
+    pixels_per_point: f32,
+    damage: &[Rect],
+    clear_color: egui::Rgba,
+) {
+    crate::profile_function!();
+    let [x, y, width, height] = scissor_box(
+        (screen_size_in_pixels[0], screen_size_in_pixels[1]),
+        pixels_per_point,
+        damage_bounds(damage),
+        ClipRounding::Outward,
+    );
+    unsafe {
+        gl.enable(glow::SCISSOR_TEST);
+        gl.scissor(x, y, width, height);
+        gl.clear_color(
+            clear_color[0],
+            clear_color[1],
+            clear_color[2],
+            clear_color[3],
+        );
+        gl.clear(glow::COLOR_BUFFER_BIT);
+        gl.disable(glow::SCISSOR_TEST);
+    }
 }
 
 pub fn clear(gl: &glow::Context, screen_size_in_pixels: [u32; 2], clear_color: egui::Rgba) {
@@ -725,20 +4866,36 @@ pub fn clear(gl: &glow::Context, screen_size_in_pixels: [u32; 2], clear_color: e
 
 impl Drop for Painter {
     fn drop(&mut self) {
-        if !self.destroyed {
-            tracing::warn!(
+        if self.destroy_on_drop && !self.context_lost && self.owns_context() {
+            self.destroy();
+        }
+        if !self.destroyed && !self.suppress_leak_warning {
+            crate::log::warn!(
                 "You forgot to call destroy() on the egui glow painter. Resources will leak!"
             );
         }
     }
 }
 
-fn set_clip_rect(
-    gl: &glow::Context,
+/// The part of the scissor box `a` that is also inside `b`.
+fn intersect_scissor_boxes(a: [i32; 4], b: [i32; 4]) -> [i32; 4] {
+    let min_x = a[0].max(b[0]);
+    let min_y = a[1].max(b[1]);
+    let max_x = (a[0] + a[2]).min(b[0] + b[2]);
+    let max_y = (a[1] + a[3]).min(b[1] + b[3]);
+    if max_x <= min_x || max_y <= min_y {
+        return [0, 0, 0, 0];
+    }
+    [min_x, min_y, max_x - min_x, max_y - min_y]
+}
+
+/// The scissor box (`[x, y, width, height]`, from the bottom left) for a clip rect in points.
+fn scissor_box(
     size_in_pixels: (u32, u32),
     pixels_per_point: f32,
     clip_rect: Rect,
-) {
+    rounding: ClipRounding,
+) -> [i32; 4] {
     // Transform clip rect to physical pixels:
     let clip_min_x = pixels_per_point * clip_rect.min.x;
     let clip_min_y = pixels_per_point * clip_rect.min.y;
@@ -746,10 +4903,24 @@ fn set_clip_rect(
     let clip_max_y = pixels_per_point * clip_rect.max.y;
 
     // Round to integer:
-    let clip_min_x = clip_min_x.round() as i32;
-    let clip_min_y = clip_min_y.round() as i32;
-    let clip_max_x = clip_max_x.round() as i32;
-    let clip_max_y = clip_max_y.round() as i32;
+    let (clip_min_x, clip_min_y, clip_max_x, clip_max_y) = match rounding {
+        ClipRounding::Round => (
+            clip_min_x.round(),
+            clip_min_y.round(),
+            clip_max_x.round(),
+            clip_max_y.round(),
+        ),
+        ClipRounding::Outward => (
+            clip_min_x.floor(),
+            clip_min_y.floor(),
+            clip_max_x.ceil(),
+            clip_max_y.ceil(),
+        ),
+    };
+    let clip_min_x = clip_min_x as i32;
+    let clip_min_y = clip_min_y as i32;
+    let clip_max_x = clip_max_x as i32;
+    let clip_max_y = clip_max_y as i32;
 
     // Clamp:
     let clip_min_x = clip_min_x.clamp(0, size_in_pixels.0 as i32);
@@ -757,12 +4928,503 @@ fn set_clip_rect(
     let clip_max_x = clip_max_x.clamp(clip_min_x, size_in_pixels.0 as i32);
     let clip_max_y = clip_max_y.clamp(clip_min_y, size_in_pixels.1 as i32);
 
-    unsafe {
-        gl.scissor(
-            clip_min_x,
-            size_in_pixels.1 as i32 - clip_max_y,
-            clip_max_x - clip_min_x,
-            clip_max_y - clip_min_y,
+    let width = clip_max_x - clip_min_x;
+    let height = clip_max_y - clip_min_y;
+    if width <= 0 || height <= 0 {
+        // Off-screen, or degenerate after rounding. Make sure nothing at all is painted:
+        return [0, 0, 0, 0];
+    }
+
+    [
+        clip_min_x,
+        size_in_pixels.1 as i32 - clip_max_y,
+        width,
+        height,
+    ]
+}
+
+#[test]
+fn test_batch_primitives() {
+    let mesh = |texture_id| {
+        let mut mesh = Mesh::with_texture(texture_id);
+        mesh.add_rect_with_uv(
+            Rect::from_min_size(egui::pos2(1.0, 2.0), egui::vec2(3.0, 4.0)),
+            Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            Color32::WHITE,
         );
+        egui::epaint::Primitive::Mesh(mesh)
+    };
+    let clipped = |clip_rect, primitive| egui::ClippedPrimitive {
+        clip_rect,
+        primitive,
+    };
+    let font = egui::TextureId::default();
+    let image = egui::TextureId::User(1);
+    let clip_a = Rect::EVERYTHING;
+    let clip_b = Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(10.0, 10.0));
+
+    let primitives = [
+        clipped(clip_a, mesh(font)),
+        clipped(clip_a, mesh(font)),
+        clipped(clip_a, mesh(image)),
+        clipped(clip_b, mesh(image)),
+        clipped(clip_b, mesh(image)),
+        clipped(clip_b, mesh(image)),
+    ];
+    let batches = batch_primitives(&primitives, None);
+
+    let meshes: Vec<(Rect, &Mesh)> = batches
+        .iter()
+        .map(|(clip_rect, primitive)| match primitive {
+            BatchedPrimitive::Mesh(mesh) => (*clip_rect, mesh.as_ref()),
+            BatchedPrimitive::Callback(_) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(meshes.len(), 3);
+
+    assert_eq!(meshes[0].0, clip_a);
+    assert_eq!(meshes[0].1.texture_id, font);
+    assert_eq!(meshes[0].1.vertices.len(), 8);
+    assert_eq!(meshes[0].1.indices, [0, 1, 2, 2, 1, 3, 4, 5, 6, 6, 5, 7]);
+    assert!(meshes[0].1.is_valid());
+
+    assert_eq!(meshes[1].1.texture_id, image);
+    assert_eq!(meshes[1].1.vertices.len(), 4);
+
+    assert_eq!(meshes[2].0, clip_b);
+    assert_eq!(meshes[2].1.vertices.len(), 12);
+    assert!(meshes[2].1.is_valid());
+}
+
+#[test]
+fn test_damage() {
+    let mesh = |min: egui::Pos2| {
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(
+            Rect::from_min_size(min, egui::vec2(10.0, 10.0)),
+            Color32::WHITE,
+        );
+        egui::ClippedPrimitive {
+            clip_rect: Rect::EVERYTHING,
+            primitive: egui::epaint::Primitive::Mesh(mesh),
+        }
+    };
+    let primitives = [
+        mesh(egui::pos2(0.0, 0.0)),
+        mesh(egui::pos2(100.0, 0.0)),
+        mesh(egui::pos2(200.0, 0.0)),
+    ];
+
+    let damage = damage_bounds(&[
+        Rect::from_min_size(egui::pos2(5.0, 5.0), egui::vec2(1.0, 1.0)),
+        Rect::from_min_size(egui::pos2(50.0, 5.0), egui::vec2(1.0, 1.0)),
+    ]);
+    assert_eq!(
+        damage,
+        Rect::from_min_max(egui::pos2(5.0, 5.0), egui::pos2(51.0, 6.0))
+    );
+    assert_eq!(batch_primitives(&primitives, Some(damage)).len(), 1);
+    assert_eq!(
+        batch_primitives(&primitives, Some(damage_bounds(&[]))).len(),
+        0
+    );
+
+    // Clipped away:
+    let mut clipped = mesh(egui::pos2(0.0, 0.0));
+    clipped.clip_rect = Rect::from_min_size(egui::pos2(0.0, 0.0), egui::vec2(2.0, 2.0));
+    assert!(!is_damaged(&clipped, damage));
+
+    assert_eq!(
+        intersect_scissor_boxes([0, 0, 10, 10], [5, 8, 10, 10]),
+        [5, 8, 5, 2]
+    );
+    assert_eq!(
+        intersect_scissor_boxes([0, 0, 10, 10], [20, 20, 10, 10]),
+        [0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn test_clamp_max_texture_side() {
+    assert_eq!(clamp_max_texture_side(2048, 8192), 2048);
+    assert_eq!(clamp_max_texture_side(16384, 8192), 8192);
+    assert_eq!(clamp_max_texture_side(0, 8192), 1);
+}
+
+#[test]
+fn test_scaled_pixels_per_point() {
+    assert_eq!(scaled_pixels_per_point(Some(1.5), 1.0), 1.5);
+    assert_eq!(scaled_pixels_per_point(Some(1.5), 2.0), 3.0);
+    assert_eq!(
+        scaled_pixels_per_point(None, 2.0),
+        1.0,
+        "raw primitives are already in pixels"
+    );
+
+    // So the clip rects of raw primitives are used as-is:
+    let clip_rect = Rect::from_min_max(egui::pos2(1.0, 2.0), egui::pos2(10.0, 20.0));
+    assert_eq!(
+        scissor_box(
+            (100, 50),
+            scaled_pixels_per_point(None, 2.0),
+            clip_rect,
+            ClipRounding::Round
+        ),
+        [1, 30, 9, 18]
+    );
+}
+
+#[test]
+fn test_font_texels_reuse_allocation() {
+    let mut image = egui::FontImage::new([64, 32]);
+    image[(1, 2)] = 1.0;
+    let rgba = UploadFormat {
+        internal_format: glow::SRGB8_ALPHA8,
+        src_format: glow::RGBA,
+        src_type: glow::UNSIGNED_BYTE,
+        bytes_per_texel: 4,
+    };
+
+    let mut texels = Vec::new();
+    font_texels(&image, 1.0, rgba, &mut texels);
+    assert_eq!(texels.len(), 64 * 32 * 4);
+    assert_eq!(&texels[4 * (2 * 64 + 1)..][..4], [255; 4]);
+    let (ptr, capacity) = (texels.as_ptr(), texels.capacity());
+
+    // Updating the same (or a smaller) region again does not allocate:
+    image[(1, 2)] = 0.0;
+    font_texels(&image, 1.0, rgba, &mut texels);
+    assert_eq!(texels.as_ptr(), ptr);
+    assert_eq!(texels.capacity(), capacity);
+    assert!(texels.iter().all(|&t| t == 0));
+
+    font_texels(
+        &image.region([0, 0], [16, 16]),
+        1.0,
+        UploadFormat::R8,
+        &mut texels,
+    );
+    assert_eq!(texels.len(), 16 * 16);
+    assert_eq!(texels.as_ptr(), ptr);
+}
+
+#[test]
+fn test_swizzled_font_texels() {
+    let mut image = egui::FontImage::new([2, 1]);
+    image[(1, 0)] = 1.0;
+    let mut texels = Vec::new();
+
+    // The color is what an sRGBA sampler returns for the RGBA font texels:
+    font_texels(&image, 0.5, UploadFormat::RG8_FONT, &mut texels);
+    let a = image.srgba_pixels(0.5).next().unwrap().a();
+    let linear = egui::Rgba::from(Color32::from_rgba_premultiplied(a, a, a, a)).r();
+    assert_eq!(
+        texels,
+        [
+            egui::epaint::color::linear_u8_from_linear_f32(linear),
+            a,
+            255,
+            255
+        ]
+    );
+}
+
+#[test]
+fn test_cached_uniform() {
+    let mut screen_size = CachedUniform::default();
+    assert!(screen_size.update([800.0, 600.0]));
+    assert!(!screen_size.update([800.0, 600.0]));
+    assert!(screen_size.update([1024.0, 600.0]));
+    assert!(!screen_size.update([1024.0, 600.0]));
+}
+
+#[test]
+fn test_add_rounded_rect() {
+    let rect = Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(100.0, 50.0));
+    let mut vertices = vec![Vertex::default(); 3];
+    let mut indices = vec![];
+
+    add_rounded_rect(&mut vertices, &mut indices, rect, egui::Rounding::none());
+    assert_eq!(vertices.len(), 3 + 4);
+    assert_eq!(indices, vec![3, 4, 5, 3, 5, 6]);
+
+    indices.clear();
+    add_rounded_rect(
+        &mut vertices,
+        &mut indices,
+        rect,
+        egui::Rounding::same(10.0),
+    );
+    let num_points = vertices.len() as u32 - 7;
+    assert!(num_points > 4);
+    assert_eq!(indices.len() as u32, 3 * (num_points - 2));
+    assert!(indices.iter().all(|&i| (7..7 + num_points).contains(&i)));
+    assert!(vertices[7..]
+        .iter()
+        .all(|v| rect.expand(0.001).contains(v.pos)));
+}
+
+#[test]
+fn test_image_from_bottom_up() {
+    let gray = Color32::from_gray;
+    let pixels = vec![gray(0), gray(1), gray(2), gray(3), gray(4), gray(5)];
+    let image = image_from_bottom_up([2, 3], pixels);
+    assert_eq!(
+        image.pixels,
+        vec![gray(4), gray(5), gray(2), gray(3), gray(0), gray(1)]
+    );
+
+    let image = image_from_bottom_up(
+        [3, 2],
+        vec![gray(0), gray(1), gray(2), gray(3), gray(4), gray(5)],
+    );
+    assert_eq!(
+        image.pixels,
+        vec![gray(3), gray(4), gray(5), gray(0), gray(1), gray(2)]
+    );
+}
+
+#[test]
+fn test_retain_texels() {
+    let id = egui::TextureId::Managed(0);
+    let mut retained = HashMap::new();
+
+    // Partial updates of unknown textures are ignored:
+    retain_texels(
+        &mut retained,
+        id,
+        Some([0, 0]),
+        [1, 1],
+        TextureOptions::LINEAR,
+        UploadFormat::R8,
+        &[9],
+    );
+    assert!(retained.is_empty());
+
+    retain_texels(
+        &mut retained,
+        id,
+        None,
+        [3, 2],
+        TextureOptions::LINEAR,
+        UploadFormat::R8,
+        &[0; 6],
+    );
+    retain_texels(
+        &mut retained,
+        id,
+        Some([1, 0]),
+        [2, 2],
+        TextureOptions::NEAREST,
+        UploadFormat::R8,
+        &[1, 2, 3, 4],
+    );
+    assert_eq!(retained[&id].data, [0, 1, 2, 0, 3, 4]);
+    assert_eq!(retained[&id].options, TextureOptions::NEAREST);
+
+    // Out of bounds:
+    retain_texels(
+        &mut retained,
+        id,
+        Some([2, 1]),
+        [2, 1],
+        TextureOptions::LINEAR,
+        UploadFormat::R8,
+        &[5, 6],
+    );
+    assert_eq!(retained[&id].data, [0, 1, 2, 0, 3, 4]);
+}
+
+#[test]
+fn test_scissor_box() {
+    let size = (100, 50);
+    let clip_rect = Rect::from_min_max(egui::pos2(1.2, 2.0), egui::pos2(10.5, 20.0));
+    assert_eq!(
+        scissor_box(size, 1.0, clip_rect, ClipRounding::Round),
+        [1, 30, 10, 18]
+    );
+    assert_eq!(
+        scissor_box(size, 1.0, clip_rect, ClipRounding::Outward),
+        [1, 30, 10, 18]
+    );
+    assert_eq!(
+        scissor_box(size, 1.5, clip_rect, ClipRounding::Round),
+        [2, 20, 14, 27]
+    );
+    assert_eq!(
+        scissor_box(size, 1.5, clip_rect, ClipRounding::Outward),
+        [1, 20, 15, 27]
+    );
+
+    // Clamped to the screen:
+    assert_eq!(
+        scissor_box(size, 10.0, clip_rect, ClipRounding::Outward),
+        [12, 0, 88, 30]
+    );
+}
+
+#[test]
+fn test_callback_clip_rect_rounding() {
+    let info = egui::PaintCallbackInfo {
+        viewport: Rect::EVERYTHING,
+        clip_rect: Rect::from_min_max(egui::pos2(1.2, 2.0), egui::pos2(10.5, 20.0)),
+        pixels_per_point: 1.5,
+        screen_size_px: [100, 50],
+    };
+    let clip_rect_px = info.clip_rect_in_pixels();
+    let clip_rect_px = [
+        clip_rect_px.left_px,
+        clip_rect_px.from_bottom_px,
+        clip_rect_px.width_px,
+        clip_rect_px.height_px,
+    ];
+    let scissor = |rounding| scissor_box((100, 50), 1.5, info.clip_rect, rounding);
+
+    assert_eq!(clip_rect_px, scissor(ClipRounding::Round));
+    assert_eq!(clip_rect_px, [2, 20, 14, 27]);
+    assert_eq!(
+        scissor(ClipRounding::Outward),
+        [1, 20, 15, 27],
+        "outward rounding covers a pixel more than `clip_rect_in_pixels`"
+    );
+}
+
+#[test]
+fn test_scissor_box_empty() {
+    let size = (100, 50);
+    let rect = |min_x, min_y, max_x, max_y| {
+        Rect::from_min_max(egui::pos2(min_x, min_y), egui::pos2(max_x, max_y))
+    };
+    for rounding in [ClipRounding::Round, ClipRounding::Outward] {
+        // Off-screen on every side:
+        for clip_rect in [
+            rect(-20.0, 10.0, -10.0, 20.0),
+            rect(110.0, 10.0, 120.0, 20.0),
+            rect(10.0, -20.0, 20.0, -10.0),
+            rect(10.0, 60.0, 20.0, 70.0),
+        ] {
+            assert_eq!(scissor_box(size, 1.0, clip_rect, rounding), [0, 0, 0, 0]);
+        }
+
+        // Inside out, or without area:
+        for clip_rect in [
+            rect(20.0, 10.0, 10.0, 20.0),
+            rect(10.0, 20.0, 20.0, 10.0),
+            rect(10.0, 10.0, 10.0, 20.0),
+            rect(10.0, 10.0, 20.0, 10.0),
+            Rect::NOTHING,
+        ] {
+            assert_eq!(scissor_box(size, 1.0, clip_rect, rounding), [0, 0, 0, 0]);
+        }
     }
+
+    // Degenerate after rounding, straddling the edge of the screen:
+    let clip_rect = rect(-0.4, 10.0, 0.4, 20.0);
+    assert_eq!(
+        scissor_box(size, 1.0, clip_rect, ClipRounding::Round),
+        [0, 0, 0, 0]
+    );
+    assert_eq!(
+        scissor_box(size, 1.0, clip_rect, ClipRounding::Outward),
+        [0, 30, 1, 10]
+    );
+}
+
+#[test]
+fn test_sampler_key() {
+    let mipmapped = TextureOptions {
+        mipmap_mode: Some(TextureFilter::Linear),
+        ..TextureOptions::LINEAR
+    };
+    assert!(SamplerKey::new(&mipmapped, None).is_mipmapped());
+    assert!(!SamplerKey::new(&TextureOptions::LINEAR, None).is_mipmapped());
+
+    // Options that don't affect sampling share a sampler:
+    let straight_alpha = TextureOptions {
+        straight_alpha: true,
+        ..TextureOptions::LINEAR
+    };
+    assert_eq!(
+        SamplerKey::new(&straight_alpha, None),
+        SamplerKey::new(&TextureOptions::LINEAR, None)
+    );
+    assert_ne!(
+        SamplerKey::new(&TextureOptions::LINEAR, Some(4.0)),
+        SamplerKey::new(&TextureOptions::LINEAR, None)
+    );
+}
+
+#[test]
+fn test_tiled_texture_shapes() {
+    let tiled = TiledTexture {
+        size: [300, 100],
+        tiles: vec![
+            TextureTile {
+                id: egui::TextureId::User(1),
+                rect: Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(0.5, 1.0)),
+            },
+            TextureTile {
+                id: egui::TextureId::User(2),
+                rect: Rect::from_min_max(egui::pos2(0.5, 0.0), egui::pos2(1.0, 1.0)),
+            },
+        ],
+    };
+    let rect = Rect::from_min_size(egui::pos2(10.0, 20.0), egui::vec2(60.0, 20.0));
+    let meshes: Vec<Mesh> = tiled
+        .shapes(rect, Color32::WHITE)
+        .into_iter()
+        .map(|shape| match shape {
+            egui::Shape::Mesh(mesh) => mesh,
+            _ => panic!("expected a mesh"),
+        })
+        .collect();
+    assert_eq!(meshes.len(), 2);
+    assert_eq!(meshes[1].texture_id, egui::TextureId::User(2));
+    assert_eq!(
+        meshes[1].calc_bounds(),
+        Rect::from_min_max(egui::pos2(40.0, 20.0), egui::pos2(70.0, 40.0))
+    );
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+#[allow(unsafe_code)]
+fn test_texture_upload_options() {
+    let gl_texture = unsafe { glow::Context::create_texture_from_gl_name(1) };
+    let mut texture = PainterTexture::native(gl_texture);
+    let nearest = TextureOptions::NEAREST;
+    let linear = TextureOptions::LINEAR;
+
+    assert!(texture.set_upload_options(linear, true));
+    assert!(
+        !texture.set_upload_options(linear, false),
+        "partial update, same filter"
+    );
+    assert!(
+        texture.set_upload_options(nearest, false),
+        "partial update, new filter"
+    );
+    assert_eq!(texture.applied_options, Some(nearest));
+    assert!(texture.set_upload_options(nearest, true), "full upload");
+    assert!(texture.set_upload_options(linear, true));
+    assert_eq!(texture.options, Some(linear));
+}
+
+#[test]
+fn test_offset_steps_without_instancing() {
+    let offsets = [egui::vec2(1.0, 2.0), egui::vec2(-3.0, 4.0)];
+    let steps: Vec<_> = offset_steps_without_instancing(&offsets).collect();
+    assert_eq!(
+        steps,
+        vec![
+            (egui::vec2(1.0, 2.0), true),
+            (egui::vec2(-3.0, 4.0), true),
+            (egui::Vec2::ZERO, false),
+        ],
+        "draws once per offset, then resets the offset for other meshes"
+    );
+    assert_eq!(
+        offset_steps_without_instancing(&[]).collect::<Vec<_>>(),
+        vec![(egui::Vec2::ZERO, false)]
+    );
 }
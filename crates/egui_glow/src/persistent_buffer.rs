@@ -0,0 +1,218 @@
+#![allow(unsafe_code)]
+
+use egui::epaint::Vertex;
+use glow::HasContext as _;
+
+use crate::check_for_gl_error;
+use crate::vao::{BufferInfo, VertexArrayObject};
+
+/// How many frames we can write ahead of the GPU.
+const NUM_SECTIONS: usize = 3;
+
+const VERTICES_PER_SECTION: usize = 1 << 16;
+const INDICES_PER_SECTION: usize = 3 * VERTICES_PER_SECTION;
+
+/// How many seconds [`PersistentMeshBuffer::begin_frame`] waits for the GPU before streaming the frame instead.
+const MAX_FENCE_WAIT_SECONDS: u32 = 3;
+
+const MAP_FLAGS: u32 = glow::MAP_WRITE_BIT | glow::MAP_PERSISTENT_BIT | glow::MAP_COHERENT_BIT;
+
+/// Vertex and index buffers that stay mapped for the lifetime of the painter,
+/// so meshes can be copied straight into them instead of calling `glBufferData` for every mesh.
+///
+/// The buffers are split into one section per frame in flight.
+/// Each section is guarded by a fence, so we never write to memory the GPU may still be reading from.
+pub(crate) struct PersistentMeshBuffer {
+    vbo: glow::Buffer,
+    element_array_buffer: glow::Buffer,
+    vao: VertexArrayObject,
+    vertex_ptr: *mut Vertex,
+    index_ptr: *mut u32,
+
+    /// The section we are writing to this frame.
+    section: usize,
+
+    /// Signaled when the GPU is done with the frame that used each section.
+    fences: [Option<glow::Fence>; NUM_SECTIONS],
+
+    /// Vertices written to the current section so far.
+    vertex_cursor: usize,
+
+    /// Indices written to the current section so far.
+    index_cursor: usize,
+
+    /// `false` if the GPU was still reading the current section when the frame began,
+    /// in which case [`Self::upload`] leaves all meshes to the streaming path.
+    writable: bool,
+}
+
+// The mapped pointers and fences are only used through the GL context they came from,
+// which must be current on whatever thread the painter is used from, just like the other GL objects.
+unsafe impl Send for PersistentMeshBuffer {}
+unsafe impl Sync for PersistentMeshBuffer {}
+
+impl PersistentMeshBuffer {
+    /// Persistent mapping needs OpenGL 4.4 or `ARB_buffer_storage`.
+    pub(crate) fn is_supported(gl: &glow::Context) -> bool {
+        if cfg!(target_arch = "wasm32") {
+            return false;
+        }
+        let version = gl.version();
+        !version.is_embedded
+            && ((version.major, version.minor) >= (4, 4)
+                || gl.supported_extensions().contains("GL_ARB_buffer_storage"))
+    }
+
+    pub(crate) unsafe fn new(
+        gl: &glow::Context,
+        buffer_infos: Vec<BufferInfo>,
+    ) -> Result<Self, String> {
+        let vertex_bytes =
+            (NUM_SECTIONS * VERTICES_PER_SECTION * std::mem::size_of::<Vertex>()) as i32;
+        let index_bytes = (NUM_SECTIONS * INDICES_PER_SECTION * std::mem::size_of::<u32>()) as i32;
+
+        let vbo = gl.create_buffer()?;
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_storage(glow::ARRAY_BUFFER, vertex_bytes, None, MAP_FLAGS);
+        let vertex_ptr = gl.map_buffer_range(glow::ARRAY_BUFFER, 0, vertex_bytes, MAP_FLAGS);
+
+        let vao = VertexArrayObject::new(gl, vbo, buffer_infos, true);
+
+        // The element array buffer binding is part of the VAO state:
+        let element_array_buffer = gl.create_buffer()?;
+        vao.bind(gl);
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(element_array_buffer));
+        gl.buffer_storage(glow::ELEMENT_ARRAY_BUFFER, index_bytes, None, MAP_FLAGS);
+        let index_ptr = gl.map_buffer_range(glow::ELEMENT_ARRAY_BUFFER, 0, index_bytes, MAP_FLAGS);
+        vao.unbind(gl);
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+        check_for_gl_error!(gl, "PersistentMeshBuffer::new");
+
+        let buffer = Self {
+            vbo,
+            element_array_buffer,
+            vao,
+            vertex_ptr: vertex_ptr.cast(),
+            index_ptr: index_ptr.cast(),
+            section: 0,
+            fences: Default::default(),
+            vertex_cursor: 0,
+            index_cursor: 0,
+            writable: true,
+        };
+
+        if vertex_ptr.is_null() || index_ptr.is_null() {
+            buffer.destroy(gl);
+            Err("Failed to map the vertex and index buffers".to_owned())
+        } else {
+            Ok(buffer)
+        }
+    }
+
+    /// Wait until the GPU is done with the section we are about to write to.
+    ///
+    /// If that takes longer than [`MAX_FENCE_WAIT_SECONDS`], give up on the section for this frame,
+    /// so that the meshes are streamed instead, and wait for it again next frame.
+    pub(crate) unsafe fn begin_frame(&mut self, gl: &glow::Context) {
+        crate::profile_function!();
+
+        self.vertex_cursor = 0;
+        self.index_cursor = 0;
+        self.writable = true;
+
+        if let Some(fence) = self.fences[self.section] {
+            let mut waited_seconds = 0;
+            loop {
+                let one_second = 1_000_000_000;
+                match gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, one_second) {
+                    glow::TIMEOUT_EXPIRED => {
+                        waited_seconds += 1;
+                        if waited_seconds == MAX_FENCE_WAIT_SECONDS {
+                            crate::log::warn!(
+                                "The GPU is still reading the mesh buffer after {} seconds; streaming the meshes of this frame instead",
+                                waited_seconds
+                            );
+                            self.writable = false;
+                            return;
+                        }
+                    }
+                    glow::WAIT_FAILED => {
+                        crate::log::warn!(
+                            "Failed to wait for the GPU to finish reading the mesh buffer"
+                        );
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+            gl.delete_sync(fence);
+            self.fences[self.section] = None;
+        }
+    }
+
+    /// Fence off the section written this frame, and move on to the next one.
+    ///
+    /// Stays on the section if the GPU was still reading it, keeping its fence to wait for next frame.
+    pub(crate) unsafe fn end_frame(&mut self, gl: &glow::Context) {
+        if !self.writable {
+            return;
+        }
+        self.fences[self.section] = gl
+            .fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0)
+            .map_err(|err| crate::log::warn!("Failed to create fence: {}", err))
+            .ok();
+        self.section = (self.section + 1) % NUM_SECTIONS;
+    }
+
+    /// Copy the vertices and indices into the current section, and bind our VAO to draw them with.
+    ///
+    /// Returns the base vertex and first index to draw them at,
+    /// or `None` if there is no room left in this frame's section, or it is still in use by the GPU.
+    pub(crate) unsafe fn upload(
+        &mut self,
+        gl: &glow::Context,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Option<(i32, usize)> {
+        if !self.writable
+            || VERTICES_PER_SECTION < self.vertex_cursor + vertices.len()
+            || INDICES_PER_SECTION < self.index_cursor + indices.len()
+        {
+            return None;
+        }
+
+        let first_vertex = self.section * VERTICES_PER_SECTION + self.vertex_cursor;
+        let first_index = self.section * INDICES_PER_SECTION + self.index_cursor;
+        std::ptr::copy_nonoverlapping(
+            vertices.as_ptr(),
+            self.vertex_ptr.add(first_vertex),
+            vertices.len(),
+        );
+        std::ptr::copy_nonoverlapping(
+            indices.as_ptr(),
+            self.index_ptr.add(first_index),
+            indices.len(),
+        );
+        self.vertex_cursor += vertices.len();
+        self.index_cursor += indices.len();
+
+        self.vao.bind(gl);
+        Some((first_vertex as i32, first_index))
+    }
+
+    pub(crate) unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+        gl.unmap_buffer(glow::ARRAY_BUFFER);
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.element_array_buffer));
+        gl.unmap_buffer(glow::ARRAY_BUFFER);
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+        for fence in self.fences.iter().flatten() {
+            gl.delete_sync(*fence);
+        }
+        self.vao.destroy(gl);
+        gl.delete_buffer(self.vbo);
+        gl.delete_buffer(self.element_array_buffer);
+    }
+}
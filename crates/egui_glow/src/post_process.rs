@@ -4,6 +4,19 @@ use crate::misc_util::{compile_shader, link_program};
 use crate::vao::BufferInfo;
 use glow::HasContext as _;
 
+/// Lets the passes from [`PostProcess::add_pass`] be written in GLSL ES 1.00
+/// and still compile with the newer shader versions that core profiles need.
+const USER_PASS_PRELUDE: &str = "#ifdef GL_ES
+    precision mediump float;
+#endif
+#ifdef NEW_SHADER_INTERFACE
+    #define varying in
+    out vec4 f_color;
+    #define gl_FragColor f_color
+    #define texture2D texture
+#endif
+";
+
 /// Uses a framebuffer to render everything in linear color space and convert it back to `sRGB`
 /// in a separate "post processing" step
 pub(crate) struct PostProcess {
@@ -13,10 +26,26 @@ pub(crate) struct PostProcess {
     vao: crate::vao::VertexArrayObject,
     is_webgl_1: bool,
     color_texture: glow::Texture,
+
+    /// The internal format of [`Self::color_texture`].
+    color_format: u32,
     depth_renderbuffer: Option<glow::Renderbuffer>,
     texture_size: (i32, i32),
     fbo: glow::Framebuffer,
     program: glow::Program,
+
+    /// Prepended to the shaders of extra passes too.
+    shader_prefix: String,
+
+    /// Run after [`Self::program`], see [`Self::add_pass`].
+    extra_passes: Vec<glow::Program>,
+
+    /// What the passes before the last one render into, alternating between the two.
+    /// Created along with the first extra pass.
+    ping_pong: Option<PingPong>,
+
+    /// Painted into instead of [`Self::fbo`] when using MSAA, see [`Self::set_samples`].
+    multisampled: Option<Multisampled>,
 }
 
 impl PostProcess {
@@ -24,8 +53,13 @@ impl PostProcess {
         gl: std::sync::Arc<glow::Context>,
         shader_prefix: &str,
         is_webgl_1: bool,
+        supports_vao: bool,
         [width, height]: [i32; 2],
+        color_format: Option<u32>,
     ) -> Result<PostProcess, String> {
+        // A zero-sized framebuffer is incomplete:
+        let (width, height) = (width.max(1), height.max(1));
+
         gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
 
         let fbo = gl.create_framebuffer()?;
@@ -58,21 +92,22 @@ impl PostProcess {
             glow::NEAREST as i32,
         );
 
-        let (internal_format, format) = if is_webgl_1 {
-            (glow::SRGB_ALPHA, glow::SRGB_ALPHA)
+        let color_format = color_format.unwrap_or(if is_webgl_1 {
+            glow::SRGB_ALPHA
         } else {
-            (glow::SRGB8_ALPHA8, glow::RGBA)
-        };
+            glow::SRGB8_ALPHA8
+        });
+        let (format, ty) = upload_format(color_format);
 
         gl.tex_image_2d(
             glow::TEXTURE_2D,
             0,
-            internal_format as i32,
+            color_format as i32,
             width,
             height,
             0,
             format,
-            glow::UNSIGNED_BYTE,
+            ty,
             None,
         );
         crate::check_for_gl_error_even_in_release!(&gl, "post process texture initialization");
@@ -107,25 +142,19 @@ impl PostProcess {
 
         // ---------------------------------------------------------
 
-        let vert_shader = compile_shader(
-            &gl,
-            glow::VERTEX_SHADER,
-            &format!(
-                "{}\n{}",
-                shader_prefix,
-                include_str!("shader/post_vertex_100es.glsl")
-            ),
-        )?;
-        let frag_shader = compile_shader(
-            &gl,
-            glow::FRAGMENT_SHADER,
-            &format!(
-                "{}\n{}",
-                shader_prefix,
-                include_str!("shader/post_fragment_100es.glsl")
-            ),
-        )?;
-        let program = link_program(&gl, [vert_shader, frag_shader].iter())?;
+        let vert_src = format!(
+            "{}\n{}",
+            shader_prefix,
+            include_str!("shader/post_vertex_100es.glsl")
+        );
+        let frag_src = format!(
+            "{}\n{}",
+            shader_prefix,
+            include_str!("shader/post_fragment_100es.glsl")
+        );
+        let vert_shader = compile_shader(&gl, glow::VERTEX_SHADER, &vert_src)?;
+        let frag_shader = compile_shader(&gl, glow::FRAGMENT_SHADER, &frag_src)?;
+        let program = link_program(&gl, &[(vert_shader, &vert_src), (frag_shader, &frag_src)])?;
 
         let positions: Vec<f32> = vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
 
@@ -153,6 +182,7 @@ impl PostProcess {
                 stride: 0,
                 offset: 0,
             }],
+            supports_vao,
         );
 
         let index_buffer = gl.create_buffer()?;
@@ -169,60 +199,203 @@ impl PostProcess {
             vao,
             is_webgl_1,
             color_texture,
+            color_format,
             depth_renderbuffer,
             texture_size: (width, height),
             fbo,
             program,
+            shader_prefix: shader_prefix.to_owned(),
+            extra_passes: Vec::new(),
+            ping_pong: None,
+            multisampled: None,
         })
     }
 
     /// What we render to.
     pub(crate) fn fbo(&self) -> glow::Framebuffer {
+        self.multisampled
+            .as_ref()
+            .map_or(self.fbo, |multisampled| multisampled.fbo)
+    }
+
+    /// The texture we render to, or resolve into when using MSAA.
+    pub(crate) fn color_texture(&self) -> glow::Texture {
+        self.color_texture
+    }
+
+    /// The internal format of [`Self::color_texture`].
+    pub(crate) fn color_format(&self) -> u32 {
+        self.color_format
+    }
+
+    /// The framebuffer with the color texture, which holds the final image after [`Self::end`].
+    ///
+    /// Unlike [`Self::fbo`], this is never multisampled, so it can be read from.
+    pub(crate) fn resolved_fbo(&self) -> glow::Framebuffer {
         self.fbo
     }
 
-    pub(crate) unsafe fn begin(&mut self, width: i32, height: i32) {
-        if (width, height) != self.texture_size {
+    /// Add a full-screen pass at the end of the chain.
+    ///
+    /// `fragment_shader` samples the output of the previous pass from `u_sampler` at `v_tc`.
+    pub(crate) unsafe fn add_pass(&mut self, fragment_shader: &str) -> Result<(), String> {
+        let vert_src = format!(
+            "{}\n{}",
+            self.shader_prefix,
+            include_str!("shader/post_vertex_100es.glsl")
+        );
+        let frag_src = format!(
+            "{}\n{}\n{}",
+            self.shader_prefix, USER_PASS_PRELUDE, fragment_shader
+        );
+        let vert_shader = compile_shader(&self.gl, glow::VERTEX_SHADER, &vert_src)?;
+        let frag_shader = compile_shader(&self.gl, glow::FRAGMENT_SHADER, &frag_src);
+        let program = frag_shader.and_then(|frag_shader| {
+            let program = link_program(
+                &self.gl,
+                &[(vert_shader, &vert_src), (frag_shader, &frag_src)],
+            );
+            self.gl.delete_shader(frag_shader);
+            program
+        });
+        self.gl.delete_shader(vert_shader);
+        let program = program?;
+
+        if self.ping_pong.is_none() {
+            match PingPong::new(&self.gl, self.texture_size) {
+                Ok(ping_pong) => self.ping_pong = Some(ping_pong),
+                Err(err) => {
+                    self.gl.delete_program(program);
+                    return Err(err);
+                }
+            }
+        }
+        self.extra_passes.push(program);
+        Ok(())
+    }
+
+    /// Remove all passes added with [`Self::add_pass`].
+    pub(crate) unsafe fn clear_passes(&mut self) {
+        for program in self.extra_passes.drain(..) {
+            self.gl.delete_program(program);
+        }
+        if let Some(ping_pong) = self.ping_pong.take() {
+            ping_pong.destroy(&self.gl);
+        }
+    }
+
+    /// Paint into a multisampled framebuffer with this many samples per pixel,
+    /// clamped to `MAX_SAMPLES`, and resolve it before the final pass.
+    ///
+    /// One sample or less turns MSAA off.
+    pub(crate) unsafe fn set_samples(&mut self, samples: u32) -> Result<(), String> {
+        if let Some(multisampled) = self.multisampled.take() {
+            multisampled.destroy(&self.gl);
+        }
+
+        if samples <= 1 {
+            return Ok(());
+        }
+        if self.is_webgl_1 {
+            return Err("Multisampled framebuffers need WebGL 2 or OpenGL ES 3.0".to_owned());
+        }
+
+        let max_samples = self.gl.get_parameter_i32(glow::MAX_SAMPLES);
+        let samples = (samples as i32).min(max_samples);
+        if samples <= 1 {
+            return Err(format!("MAX_SAMPLES is only {}", max_samples));
+        }
+
+        self.multisampled = Some(Multisampled::new(
+            &self.gl,
+            samples,
+            self.color_format,
+            self.depth_renderbuffer.is_some(),
+            self.texture_size,
+        )?);
+        Ok(())
+    }
+
+    /// The size of [`Self::fbo`], as of the last [`Self::resize`].
+    pub(crate) fn size(&self) -> [u32; 2] {
+        [self.texture_size.0 as u32, self.texture_size.1 as u32]
+    }
+
+    /// Does [`Self::fbo`] have a depth buffer attached?
+    pub(crate) fn has_depth_buffer(&self) -> bool {
+        self.depth_renderbuffer.is_some()
+    }
+
+    /// Clear the color and depth buffers of [`Self::fbo`], which must be bound.
+    ///
+    /// The color is cleared to the current clear color, and the depth to the current clear depth.
+    pub(crate) unsafe fn clear(&self) {
+        if self.depth_renderbuffer.is_some() {
+            // A callback may have disabled depth writes, which would also prevent clearing:
+            self.gl.depth_mask(true);
             self.gl
-                .bind_texture(glow::TEXTURE_2D, Some(self.color_texture));
-            self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
-            let (internal_format, format) = if self.is_webgl_1 {
-                (glow::SRGB_ALPHA, glow::SRGB_ALPHA)
-            } else {
-                (glow::SRGB8_ALPHA8, glow::RGBA)
-            };
-            self.gl.tex_image_2d(
-                glow::TEXTURE_2D,
-                0,
-                internal_format as i32,
+                .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+        } else {
+            self.gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+    }
+
+    /// Reallocate the color buffer and the other attachments in place, for a new framebuffer size.
+    ///
+    /// The framebuffers and textures stay the same, only their contents are lost.
+    pub(crate) unsafe fn resize(&mut self, [width, height]: [u32; 2]) {
+        crate::profile_function!();
+        // A zero-sized framebuffer is incomplete:
+        let (width, height) = (width.max(1) as i32, height.max(1) as i32);
+
+        self.gl
+            .bind_texture(glow::TEXTURE_2D, Some(self.color_texture));
+        self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+        let (format, ty) = upload_format(self.color_format);
+        self.gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            self.color_format as i32,
+            width,
+            height,
+            0,
+            format,
+            ty,
+            None,
+        );
+        self.gl.bind_texture(glow::TEXTURE_2D, None);
+
+        if let Some(depth_renderbuffer) = self.depth_renderbuffer {
+            self.gl
+                .bind_renderbuffer(glow::RENDERBUFFER, Some(depth_renderbuffer));
+            self.gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::DEPTH_COMPONENT16,
                 width,
                 height,
-                0,
-                format,
-                glow::UNSIGNED_BYTE,
-                None,
             );
-            self.gl.bind_texture(glow::TEXTURE_2D, None);
-
-            if let Some(depth_renderbuffer) = self.depth_renderbuffer {
-                self.gl
-                    .bind_renderbuffer(glow::RENDERBUFFER, Some(depth_renderbuffer));
-                self.gl.renderbuffer_storage(
-                    glow::RENDERBUFFER,
-                    glow::DEPTH_COMPONENT16,
-                    width,
-                    height,
-                );
-                self.gl.bind_renderbuffer(glow::RENDERBUFFER, None);
-            }
+            self.gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+        }
 
-            self.texture_size = (width, height);
+        if let Some(multisampled) = &self.multisampled {
+            multisampled.allocate(&self.gl, width, height);
         }
+        if let Some(ping_pong) = &self.ping_pong {
+            ping_pong.allocate(&self.gl, width, height);
+        }
+
+        self.texture_size = (width, height);
 
-        check_for_gl_error!(&self.gl, "PostProcess::begin");
+        check_for_gl_error!(&self.gl, "PostProcess::resize");
     }
 
     pub(crate) unsafe fn bind(&self) {
+        if let Some(multisampled) = &self.multisampled {
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, Some(multisampled.fbo));
+            return;
+        }
+
         self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
 
         self.gl.framebuffer_texture_2d(
@@ -243,26 +416,73 @@ impl PostProcess {
         check_for_gl_error!(&self.gl, "PostProcess::bind");
     }
 
-    pub(crate) unsafe fn end(&self) {
-        self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+    /// Finish the frame in [`Self::color_texture`], and unless `present` is `false`, draw it to the screen,
+    /// i.e. to `screen_fbo`, or the default framebuffer if that is `None`.
+    pub(crate) unsafe fn end(&self, screen_fbo: Option<glow::Framebuffer>, present: bool) {
         self.gl.disable(glow::SCISSOR_TEST);
 
-        self.gl.use_program(Some(self.program));
+        if let Some(multisampled) = &self.multisampled {
+            let (width, height) = self.texture_size;
+            self.gl
+                .bind_framebuffer(glow::READ_FRAMEBUFFER, Some(multisampled.fbo));
+            self.gl
+                .bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(self.fbo));
+            self.gl.blit_framebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                glow::COLOR_BUFFER_BIT,
+                glow::NEAREST,
+            );
+            check_for_gl_error!(&self.gl, "PostProcess::end resolve");
+        }
+
+        if !present {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, screen_fbo);
+            return;
+        }
+
+        if !cfg!(target_arch = "wasm32") {
+            // The built-in pass encodes to sRGB itself:
+            self.gl.disable(glow::FRAMEBUFFER_SRGB);
+        }
 
         self.gl.active_texture(glow::TEXTURE0);
-        self.gl
-            .bind_texture(glow::TEXTURE_2D, Some(self.color_texture));
-        let u_sampler_loc = self
-            .gl
-            .get_uniform_location(self.program, "u_sampler")
-            .unwrap();
-        self.gl.uniform_1_i32(Some(&u_sampler_loc), 0);
         self.vao.bind(&self.gl);
-
         self.gl
             .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
-        self.gl
-            .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_BYTE, 0);
+
+        // Each pass samples the output of the one before it, and the last one draws to the screen:
+        let mut source = self.color_texture;
+        let last_pass = self.extra_passes.len();
+        let passes = std::iter::once(self.program).chain(self.extra_passes.iter().copied());
+        for (i, program) in passes.enumerate() {
+            let target = match &self.ping_pong {
+                Some(ping_pong) if i < last_pass => Some(ping_pong.targets[i % 2]),
+                _ => None,
+            };
+            self.gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                target.map_or(screen_fbo, |(fbo, _)| Some(fbo)),
+            );
+
+            self.gl.use_program(Some(program));
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(source));
+            let u_sampler_loc = self.gl.get_uniform_location(program, "u_sampler");
+            self.gl.uniform_1_i32(u_sampler_loc.as_ref(), 0);
+            self.gl
+                .draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_BYTE, 0);
+
+            if let Some((_, texture)) = target {
+                source = texture;
+            }
+        }
+
         self.vao.unbind(&self.gl);
         self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
         self.gl.bind_texture(glow::TEXTURE_2D, None);
@@ -280,5 +500,182 @@ impl PostProcess {
         if let Some(depth_renderbuffer) = self.depth_renderbuffer {
             self.gl.delete_renderbuffer(depth_renderbuffer);
         }
+        if let Some(multisampled) = &self.multisampled {
+            multisampled.destroy(&self.gl);
+        }
+        for &program in &self.extra_passes {
+            self.gl.delete_program(program);
+        }
+        if let Some(ping_pong) = &self.ping_pong {
+            ping_pong.destroy(&self.gl);
+        }
+    }
+}
+
+/// Two framebuffers with a color texture each, for the passes of [`PostProcess`] to render into in turn.
+///
+/// They hold the sRGB-encoded output of the built-in pass as-is, so they are not sRGB textures.
+struct PingPong {
+    targets: [(glow::Framebuffer, glow::Texture); 2],
+}
+
+impl PingPong {
+    unsafe fn new(gl: &glow::Context, (width, height): (i32, i32)) -> Result<Self, String> {
+        let mut targets = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let texture = gl.create_texture()?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            for (parameter, value) in [
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+
+            let fbo = gl.create_framebuffer()?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            targets.push((fbo, texture));
+        }
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+        let ping_pong = Self {
+            targets: [targets[0], targets[1]],
+        };
+        ping_pong.allocate(gl, width, height);
+        Ok(ping_pong)
+    }
+
+    unsafe fn allocate(&self, gl: &glow::Context, width: i32, height: i32) {
+        for (_, texture) in self.targets {
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA as i32,
+                width,
+                height,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+        }
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        check_for_gl_error!(gl, "PingPong::allocate");
+    }
+
+    unsafe fn destroy(&self, gl: &glow::Context) {
+        for (fbo, texture) in self.targets {
+            gl.delete_framebuffer(fbo);
+            gl.delete_texture(texture);
+        }
+    }
+}
+
+/// A multisampled framebuffer to paint into, see [`PostProcess::set_samples`].
+struct Multisampled {
+    fbo: glow::Framebuffer,
+    color_renderbuffer: glow::Renderbuffer,
+    depth_renderbuffer: Option<glow::Renderbuffer>,
+    samples: i32,
+    color_format: u32,
+}
+
+impl Multisampled {
+    unsafe fn new(
+        gl: &glow::Context,
+        samples: i32,
+        color_format: u32,
+        with_depth_buffer: bool,
+        (width, height): (i32, i32),
+    ) -> Result<Self, String> {
+        let multisampled = Self {
+            fbo: gl.create_framebuffer()?,
+            color_renderbuffer: gl.create_renderbuffer()?,
+            depth_renderbuffer: if with_depth_buffer {
+                Some(gl.create_renderbuffer()?)
+            } else {
+                None
+            },
+            samples,
+            color_format,
+        };
+        multisampled.allocate(gl, width, height);
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(multisampled.fbo));
+        gl.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::RENDERBUFFER,
+            Some(multisampled.color_renderbuffer),
+        );
+        gl.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            glow::DEPTH_ATTACHMENT,
+            glow::RENDERBUFFER,
+            multisampled.depth_renderbuffer,
+        );
+        let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+        if status == glow::FRAMEBUFFER_COMPLETE {
+            Ok(multisampled)
+        } else {
+            multisampled.destroy(gl);
+            Err(format!(
+                "Multisampled framebuffer is incomplete: status 0x{:X}",
+                status
+            ))
+        }
+    }
+
+    unsafe fn allocate(&self, gl: &glow::Context, width: i32, height: i32) {
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(self.color_renderbuffer));
+        gl.renderbuffer_storage_multisample(
+            glow::RENDERBUFFER,
+            self.samples,
+            self.color_format,
+            width,
+            height,
+        );
+        if let Some(depth_renderbuffer) = self.depth_renderbuffer {
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth_renderbuffer));
+            gl.renderbuffer_storage_multisample(
+                glow::RENDERBUFFER,
+                self.samples,
+                glow::DEPTH_COMPONENT16,
+                width,
+                height,
+            );
+        }
+        gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+        check_for_gl_error!(gl, "Multisampled::allocate");
+    }
+
+    unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_framebuffer(self.fbo);
+        gl.delete_renderbuffer(self.color_renderbuffer);
+        if let Some(depth_renderbuffer) = self.depth_renderbuffer {
+            gl.delete_renderbuffer(depth_renderbuffer);
+        }
+    }
+}
+
+/// The format and type to allocate a color texture with the given internal format.
+fn upload_format(internal_format: u32) -> (u32, u32) {
+    match internal_format {
+        glow::SRGB_ALPHA => (glow::SRGB_ALPHA, glow::UNSIGNED_BYTE),
+        glow::RGBA16F => (glow::RGBA, glow::HALF_FLOAT),
+        glow::RGBA32F => (glow::RGBA, glow::FLOAT),
+        _ => (glow::RGBA, glow::UNSIGNED_BYTE),
     }
 }
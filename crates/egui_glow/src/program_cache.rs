@@ -0,0 +1,233 @@
+#![allow(unsafe_code)]
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::c_void,
+    hash::{Hash, Hasher},
+};
+
+use glow::HasContext as _;
+
+use crate::PainterError;
+
+/// Loads and stores the linked shader program of a [`crate::Painter`] between runs,
+/// see [`crate::Painter::new_with_program_cache`].
+pub struct ProgramCache<'a> {
+    /// Returns the address of an OpenGL function, like the loader the `glow::Context` was created with.
+    ///
+    /// glow has no wrappers for `glGetProgramBinary` and `glProgramBinary`, so we load them with this.
+    pub get_proc_address: &'a dyn Fn(&str) -> *const c_void,
+
+    /// The bytes given to [`Self::store`] on a previous run, if any.
+    pub cached: Option<&'a [u8]>,
+
+    /// Called with the bytes to cache whenever the program had to be compiled.
+    pub store: &'a mut dyn FnMut(Vec<u8>),
+}
+
+type GetProgramiv = unsafe extern "system" fn(u32, u32, *mut i32);
+type GetProgramBinary = unsafe extern "system" fn(u32, i32, *mut i32, *mut u32, *mut c_void);
+type ProgramBinary = unsafe extern "system" fn(u32, u32, *const c_void, i32);
+type ProgramParameteri = unsafe extern "system" fn(u32, u32, i32);
+
+/// The entry points of `GL_ARB_get_program_binary`, and `glGetProgramiv`, which glow only wraps for some parameters.
+struct ProgramBinaryFns {
+    get_program_iv: GetProgramiv,
+    get_program_binary: GetProgramBinary,
+    program_binary: ProgramBinary,
+    program_parameter_i: ProgramParameteri,
+}
+
+impl ProgramBinaryFns {
+    /// `None` unless the context has OpenGL 4.1, `GL_ARB_get_program_binary` or OpenGL ES 3.0,
+    /// and the driver supports at least one binary format.
+    unsafe fn load(
+        gl: &glow::Context,
+        get_proc_address: &dyn Fn(&str) -> *const c_void,
+    ) -> Option<Self> {
+        let version = gl.version();
+        let supported = if version.is_embedded {
+            version.major >= 3
+        } else {
+            (version.major, version.minor) >= (4, 1)
+                || gl
+                    .supported_extensions()
+                    .contains("GL_ARB_get_program_binary")
+        };
+        if !supported || gl.get_parameter_i32(glow::NUM_PROGRAM_BINARY_FORMATS) <= 0 {
+            return None;
+        }
+
+        let load = |name| {
+            let function = get_proc_address(name);
+            (!function.is_null()).then(|| function)
+        };
+        Some(Self {
+            get_program_iv: std::mem::transmute::<*const c_void, GetProgramiv>(load(
+                "glGetProgramiv",
+            )?),
+            get_program_binary: std::mem::transmute::<*const c_void, GetProgramBinary>(load(
+                "glGetProgramBinary",
+            )?),
+            program_binary: std::mem::transmute::<*const c_void, ProgramBinary>(load(
+                "glProgramBinary",
+            )?),
+            program_parameter_i: std::mem::transmute::<*const c_void, ProgramParameteri>(load(
+                "glProgramParameteri",
+            )?),
+        })
+    }
+}
+
+/// Load the program from [`ProgramCache::cached`] if it was stored for the same sources and driver,
+/// or else compile it with `compile` and hand it to [`ProgramCache::store`].
+///
+/// `sources` are the complete shader sources, and `compile` is called with a function
+/// to call with the program just before it is linked.
+pub(crate) unsafe fn load_or_compile(
+    gl: &glow::Context,
+    cache: &mut ProgramCache<'_>,
+    sources: &[String],
+    compile: impl FnOnce(&dyn Fn(glow::Program)) -> Result<glow::Program, PainterError>,
+) -> Result<glow::Program, PainterError> {
+    crate::profile_function!();
+
+    let fns = if let Some(fns) = ProgramBinaryFns::load(gl, cache.get_proc_address) {
+        fns
+    } else {
+        crate::log::debug!("Program binaries not supported.");
+        return compile(&|_| ());
+    };
+
+    let key = cache_key(gl, sources);
+    if let Some((format, binary)) = cache.cached.and_then(|cached| decode(cached, key)) {
+        if let Ok(program) = gl.create_program() {
+            (fns.program_binary)(
+                gl_name(program),
+                format,
+                binary.as_ptr().cast(),
+                binary.len() as i32,
+            );
+            if gl.get_program_link_status(program) {
+                return Ok(program);
+            }
+            // E.g. after a driver update.
+            crate::log::debug!(
+                "The driver rejected the cached program binary. Compiling it again."
+            );
+            gl.delete_program(program);
+            // Which may have raised an error (e.g. for an unknown format) that isn't ours to report:
+            gl.get_error();
+        }
+    }
+
+    let program = compile(&|program| {
+        (fns.program_parameter_i)(
+            gl_name(program),
+            glow::PROGRAM_BINARY_RETRIEVABLE_HINT,
+            glow::TRUE as i32,
+        );
+    })?;
+
+    let name = gl_name(program);
+    let mut length = 0;
+    (fns.get_program_iv)(name, glow::PROGRAM_BINARY_LENGTH, &mut length);
+    if length > 0 {
+        let mut binary = vec![0_u8; length as usize];
+        let mut written = 0;
+        let mut format = 0;
+        (fns.get_program_binary)(
+            name,
+            length,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr().cast(),
+        );
+        if written > 0 {
+            binary.truncate(written as usize);
+            (cache.store)(encode(key, format, &binary));
+        }
+    }
+    Ok(program)
+}
+
+/// Identifies what a program binary is for: the shader sources and the driver.
+fn cache_key(gl: &glow::Context, sources: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    sources.hash(&mut hasher);
+    for parameter in [glow::VENDOR, glow::RENDERER, glow::VERSION] {
+        gl.get_parameter_string(parameter).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// The bytes for [`ProgramCache::store`]: `key` and `format`, followed by the program binary.
+fn encode(key: u64, format: u32, binary: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(12 + binary.len());
+    bytes.extend_from_slice(&key.to_le_bytes());
+    bytes.extend_from_slice(&format.to_le_bytes());
+    bytes.extend_from_slice(binary);
+    bytes
+}
+
+/// The format and program binary of bytes from [`encode`], or `None` if they are for another `key`.
+fn decode(bytes: &[u8], key: u64) -> Option<(u32, &[u8])> {
+    let (cached_key, bytes) = split_first_chunk::<8>(bytes)?;
+    let (format, binary) = split_first_chunk::<4>(bytes)?;
+    (u64::from_le_bytes(cached_key) == key && !binary.is_empty())
+        .then(|| (u32::from_le_bytes(format), binary))
+}
+
+fn split_first_chunk<const N: usize>(bytes: &[u8]) -> Option<([u8; N], &[u8])> {
+    if bytes.len() < N {
+        return None;
+    }
+    let (chunk, rest) = bytes.split_at(N);
+    Some((chunk.try_into().ok()?, rest))
+}
+
+/// The OpenGL name of a glow object, e.g. a [`glow::Program`], which glow doesn't expose.
+///
+/// The native glow objects wrap just their name, and derive [`Hash`], which hashes it as a `u32`.
+fn gl_name(object: impl Hash) -> u32 {
+    /// Keeps the `u32` it is given.
+    #[derive(Default)]
+    struct NameHasher(u32);
+
+    impl Hasher for NameHasher {
+        fn finish(&self) -> u64 {
+            u64::from(self.0)
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {
+            unreachable!("glow objects hash their name as a u32");
+        }
+
+        fn write_u32(&mut self, name: u32) {
+            self.0 = name;
+        }
+    }
+
+    let mut hasher = NameHasher::default();
+    object.hash(&mut hasher);
+    hasher.0
+}
+
+#[test]
+fn test_gl_name() {
+    let texture = unsafe { glow::Context::create_texture_from_gl_name(42) };
+    assert_eq!(gl_name(texture), 42);
+}
+
+#[test]
+fn test_encode_decode() {
+    let bytes = encode(7, 0x8741, &[1, 2, 3]);
+    assert_eq!(decode(&bytes, 7), Some((0x8741, &[1_u8, 2, 3][..])));
+    assert_eq!(
+        decode(&bytes, 8),
+        None,
+        "for other sources or another driver"
+    );
+    assert_eq!(decode(&bytes[..12], 7), None, "without a binary");
+    assert_eq!(decode(&[1, 2, 3], 7), None);
+}
@@ -0,0 +1,126 @@
+#![allow(unsafe_code)]
+
+use egui::Color32;
+use glow::HasContext as _;
+
+use crate::check_for_gl_error;
+
+/// A screenshot that is being copied into a pixel buffer object,
+/// so that we don't have to stall until the GPU is done painting, like `glReadPixels` into memory does.
+///
+/// See [`crate::Painter::request_screenshot`].
+pub(crate) struct PendingScreenshot {
+    buffer: glow::Buffer,
+
+    /// Signaled once the copy into [`Self::buffer`] is done.
+    fence: glow::Fence,
+
+    size: [usize; 2],
+}
+
+// The fence is only used through the GL context it came from,
+// which must be current on whatever thread the painter is used from, just like the other GL objects.
+unsafe impl Send for PendingScreenshot {}
+unsafe impl Sync for PendingScreenshot {}
+
+impl PendingScreenshot {
+    /// Pixel buffer objects and fences need OpenGL 3.2, OpenGL ES 3.0 or WebGL 2.
+    pub(crate) fn is_supported(gl: &glow::Context) -> bool {
+        let version = gl.version();
+        if version.is_embedded {
+            version.major >= 3
+        } else {
+            (version.major, version.minor) >= (3, 2)
+        }
+    }
+
+    /// Start copying the bound framebuffer.
+    pub(crate) unsafe fn start(
+        gl: &glow::Context,
+        [width, height]: [u32; 2],
+    ) -> Result<Self, String> {
+        crate::profile_function!();
+
+        let size = [width as usize, height as usize];
+        let byte_len = size[0] * size[1] * std::mem::size_of::<Color32>();
+
+        let buffer = gl.create_buffer()?;
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+        gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, byte_len as i32, glow::STREAM_READ);
+        gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+        gl.read_pixels(
+            0,
+            0,
+            width as _,
+            height as _,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            glow::PixelPackData::BufferOffset(0),
+        );
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        check_for_gl_error!(gl, "PendingScreenshot::start");
+
+        match gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0) {
+            Ok(fence) => Ok(Self {
+                buffer,
+                fence,
+                size,
+            }),
+            Err(err) => {
+                gl.delete_buffer(buffer);
+                Err(err)
+            }
+        }
+    }
+
+    pub(crate) fn size(&self) -> [usize; 2] {
+        self.size
+    }
+
+    /// Has the copy finished? Never blocks.
+    pub(crate) unsafe fn is_ready(&self, gl: &glow::Context) -> bool {
+        matches!(
+            gl.client_wait_sync(self.fence, glow::SYNC_FLUSH_COMMANDS_BIT, 0),
+            glow::ALREADY_SIGNALED | glow::CONDITION_SATISFIED
+        )
+    }
+
+    /// Read back the pixels, bottom row first, and delete the buffer.
+    ///
+    /// Only call this once [`Self::is_ready`], or it will stall.
+    pub(crate) unsafe fn finish(self, gl: &glow::Context) -> Vec<Color32> {
+        crate::profile_function!();
+
+        let mut pixels = vec![Color32::TRANSPARENT; self.size[0] * self.size[1]];
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut pixels);
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.buffer));
+
+        // WebGL can't map buffers, and OpenGL ES can't read them any other way:
+        if cfg!(target_arch = "wasm32") {
+            gl.get_buffer_sub_data(glow::PIXEL_PACK_BUFFER, 0, bytes);
+        } else {
+            let ptr = gl.map_buffer_range(
+                glow::PIXEL_PACK_BUFFER,
+                0,
+                bytes.len() as i32,
+                glow::MAP_READ_BIT,
+            );
+            if ptr.is_null() {
+                crate::log::warn!("Failed to map the screenshot buffer");
+            } else {
+                std::ptr::copy_nonoverlapping(ptr, bytes.as_mut_ptr(), bytes.len());
+                gl.unmap_buffer(glow::PIXEL_PACK_BUFFER);
+            }
+        }
+
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        check_for_gl_error!(gl, "PendingScreenshot::finish");
+        self.destroy(gl);
+        pixels
+    }
+
+    pub(crate) unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_sync(self.fence);
+        gl.delete_buffer(self.buffer);
+    }
+}
@@ -4,6 +4,9 @@
 
 uniform sampler2D u_sampler;
 
+// Set when the texture has straight alpha, which we premultiply after decoding it to linear space.
+uniform bool u_straight_alpha;
+
 #ifdef NEW_SHADER_INTERFACE
     in vec4 v_rgba;
     in vec2 v_tc;
@@ -16,11 +19,92 @@ uniform sampler2D u_sampler;
     varying vec2 v_tc;
 #endif
 
+#ifdef R8_FONT_TEXTURE
+    // Set when sampling the single-channel font atlas.
+    uniform bool u_font_r8;
+
+    // 0-1 linear  from  0-1 sRGB, the same decoding an sRGB texture sampler does.
+    float linear_from_srgb_r8(float srgb) {
+        if (srgb <= 0.04045) {
+            return srgb / 12.92;
+        } else {
+            return pow((srgb + 0.055) / 1.055, 2.4);
+        }
+    }
+
+    vec4 sample_texture(vec2 tc) {
+        vec4 texel = texture2D(u_sampler, tc);
+        if (u_font_r8) {
+            // The font atlas stores premultiplied white as a single channel.
+            // Expand it to what an sRGBA texture sampler would have returned:
+            texel = vec4(vec3(linear_from_srgb_r8(texel.r)), texel.r);
+        }
+        return texel;
+    }
+#else
+    vec4 sample_texture(vec2 tc) {
+        return texture2D(u_sampler, tc);
+    }
+#endif
+
+#ifdef HDR_OUTPUT
+    // Relative to SDR white, see `Painter::set_hdr_scale`.
+    uniform float u_hdr_scale;
+#endif
+
+#ifdef DITHER
+    // See `Painter::set_dithering`.
+    uniform bool u_dither;
+
+    // 0-3, the entry of the 2x2 Bayer matrix at a position with 0 or 1 in each coordinate.
+    float bayer2(vec2 p) {
+        return mod(2.0 * p.x + 3.0 * p.y, 4.0);
+    }
+
+    // -0.5 to 0.5, from the 4x4 Bayer matrix tiled over the screen.
+    float dither_offset() {
+        vec2 p = mod(floor(gl_FragCoord.xy), 4.0);
+        float index = 4.0 * bayer2(mod(p, 2.0)) + bayer2(floor(p / 2.0));
+        return (index + 0.5) / 16.0 - 0.5;
+    }
+#endif
+
+// See `Painter::set_color_transform`.
+uniform mat4 u_color_transform;
+
+#ifdef OVERDRAW_DEBUG
+    // See `Painter::set_overdraw_debug`.
+    uniform bool u_overdraw;
+
+    // Every layer adds this, so 16 layers add up to white:
+    const vec4 OVERDRAW_COLOR = vec4(vec3(1.0 / 16.0), 1.0 / 16.0);
+#endif
+
 #ifdef SRGB_SUPPORTED
     void main() {
         // The texture sampler is sRGB aware, and OpenGL already expects linear rgba output
         // so no need for any sRGB conversions here:
-        gl_FragColor = v_rgba * texture2D(u_sampler, v_tc);
+        vec4 texture_rgba = sample_texture(v_tc);
+        if (u_straight_alpha) {
+            texture_rgba.rgb *= texture_rgba.a;
+        }
+        gl_FragColor = u_color_transform * (v_rgba * texture_rgba);
+        #ifdef DITHER
+            if (u_dither) {
+                // Perturb by up to half a step of the 8-bit sRGB framebuffer,
+                // in (approximately) sRGB space, where the quantization happens:
+                vec3 srgb = pow(gl_FragColor.rgb, vec3(1.0 / 2.2)) + vec3(dither_offset() / 255.0);
+                gl_FragColor.rgb = pow(max(srgb, vec3(0.0)), vec3(2.2));
+            }
+        #endif
+        #ifdef HDR_OUTPUT
+            gl_FragColor.rgb *= u_hdr_scale;
+        #endif
+        #ifdef OVERDRAW_DEBUG
+            if (u_overdraw) {
+                gl_FragColor = OVERDRAW_COLOR;
+            }
+        #endif
     }
 #else
     // 0-255 sRGB  from  0-1 linear
@@ -49,9 +133,12 @@ uniform sampler2D u_sampler;
 
     void main() {
         // We must decode the colors, since WebGL1 doesn't come with sRGBA textures:
-        vec4 texture_rgba = linear_from_srgba(texture2D(u_sampler, v_tc) * 255.0);
+        vec4 texture_rgba = linear_from_srgba(sample_texture(v_tc) * 255.0);
+        if (u_straight_alpha) {
+            texture_rgba.rgb *= texture_rgba.a;
+        }
         /// Multiply vertex color with texture color (in linear space).
-        gl_FragColor = v_rgba * texture_rgba;
+        gl_FragColor = u_color_transform * (v_rgba * texture_rgba);
 
         // WebGL1 doesn't support linear blending in the framebuffer,
         // so we do a hack here where we change the premultiplied alpha
@@ -72,5 +159,17 @@ uniform sampler2D u_sampler;
         if (gl_FragColor.a > 0.0) {
             gl_FragColor.rgb *= gl_FragColor.a;
         }
+
+        #ifdef DITHER
+            if (u_dither) {
+                // Perturb by up to half a step of the 8-bit framebuffer:
+                gl_FragColor.rgb = max(gl_FragColor.rgb + vec3(dither_offset() / 255.0), vec3(0.0));
+            }
+        #endif
+        #ifdef OVERDRAW_DEBUG
+            if (u_overdraw) {
+                gl_FragColor = OVERDRAW_COLOR;
+            }
+        #endif
     }
 #endif
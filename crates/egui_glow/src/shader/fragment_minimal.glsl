@@ -0,0 +1,30 @@
+// The fragment shader of `Painter::new_with_fallback_shader`, for drivers that reject `fragment.glsl`.
+// Only `#version 100` and `#version 120`, so no new shader interface, and no optional features.
+
+#ifdef GL_ES
+    precision mediump float;
+#endif
+
+uniform sampler2D u_sampler;
+
+// Set when the texture has straight alpha.
+uniform bool u_straight_alpha;
+
+varying vec4 v_rgba;
+varying vec2 v_tc;
+
+void main() {
+    vec4 texture_rgba = texture2D(u_sampler, v_tc);
+    if (u_straight_alpha) {
+        texture_rgba.rgb *= texture_rgba.a;
+    }
+    vec4 rgba = v_rgba;
+    #ifndef SRGB_SUPPORTED
+        // Neither the texture nor the framebuffer decode sRGB, so multiply and blend in gamma space.
+        // The vertex shader gave us linear colors, so encode them again (approximately):
+        if (rgba.a > 0.0) {
+            rgba.rgb = pow(rgba.rgb / rgba.a, vec3(1.0 / 2.2)) * rgba.a;
+        }
+    #endif
+    gl_FragColor = rgba * texture_rgba;
+}
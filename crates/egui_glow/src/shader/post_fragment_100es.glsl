@@ -1,6 +1,16 @@
-precision mediump float;
+#ifdef GL_ES
+    precision mediump float;
+#endif
 uniform sampler2D u_sampler;
-varying vec2 v_tc;
+
+#ifdef NEW_SHADER_INTERFACE
+    in vec2 v_tc;
+    out vec4 f_color;
+    #define gl_FragColor f_color
+    #define texture2D texture
+#else
+    varying vec2 v_tc;
+#endif
 
 // 0-255 sRGB  from  0-1 linear
 vec3 srgb_from_linear(vec3 rgb) {
@@ -1,6 +1,14 @@
-precision mediump float;
-attribute vec2 a_pos;
-varying vec2 v_tc;
+#ifdef GL_ES
+    precision mediump float;
+#endif
+
+#ifdef NEW_SHADER_INTERFACE
+    in vec2 a_pos;
+    out vec2 v_tc;
+#else
+    attribute vec2 a_pos;
+    varying vec2 v_tc;
+#endif
 
 void main() {
     gl_Position = vec4(a_pos * 2. - 1., 0.0, 1.0);
@@ -16,6 +16,7 @@ uniform vec2 u_screen_size;
 I vec2 a_pos;
 I vec4 a_srgba; // 0-255 sRGB
 I vec2 a_tc;
+I vec2 a_offset; // Per instance, see `Painter::paint_instanced_mesh`. Zero otherwise.
 O vec4 v_rgba;
 O vec2 v_tc;
 
@@ -32,9 +33,10 @@ vec4 linear_from_srgba(vec4 srgba) {
 }
 
 void main() {
+    vec2 pos = a_pos + a_offset;
     gl_Position = vec4(
-                      2.0 * a_pos.x / u_screen_size.x - 1.0,
-                      1.0 - 2.0 * a_pos.y / u_screen_size.y,
+                      2.0 * pos.x / u_screen_size.x - 1.0,
+                      1.0 - 2.0 * pos.y / u_screen_size.y,
                       0.0,
                       1.0);
     // egui encodes vertex colors in gamma space, so we must decode the colors here:
@@ -2,12 +2,23 @@
 
 use std::convert::TryInto;
 
+/// The version of GLSL that the shaders are compiled as.
+///
+/// Normally detected from the context, but see [`crate::Painter::new_with_shader_version`].
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[allow(dead_code)]
-pub(crate) enum ShaderVersion {
+pub enum ShaderVersion {
+    /// GLSL 1.20, for OpenGL 2.1.
     Gl120,
+    /// GLSL 1.40, for OpenGL 3.1 and later.
     Gl140,
+    /// Core profile, GLSL 3.30 up to 4.00.
+    Gl330Core,
+    /// Core profile, GLSL 4.10 or later.
+    Gl410Core,
+    /// GLSL ES 1.00, for OpenGL ES 2.0 and WebGL 1.
     Es100,
+    /// GLSL ES 3.00, for OpenGL ES 3.0 and WebGL 2.
     Es300,
 }
 
@@ -16,17 +27,21 @@ impl ShaderVersion {
         use glow::HasContext as _;
         let shading_lang_string =
             unsafe { gl.get_parameter_string(glow::SHADING_LANGUAGE_VERSION) };
-        let shader_version = Self::parse(&shading_lang_string);
-        tracing::debug!(
-            "Shader version: {:?} ({:?}).",
+        let core_profile = is_core_profile(gl);
+        let shader_version = Self::parse(&shading_lang_string, core_profile);
+        crate::log::debug!(
+            "Shader version: {:?} ({:?}, core profile: {}).",
             shader_version,
-            shading_lang_string
+            shading_lang_string,
+            core_profile
         );
         shader_version
     }
 
+    /// `core_profile` is whether the context is a desktop core profile context,
+    /// which may reject the older GLSL versions.
     #[inline]
-    pub(crate) fn parse(glsl_ver: &str) -> Self {
+    pub(crate) fn parse(glsl_ver: &str, core_profile: bool) -> Self {
         let start = glsl_ver.find(|c| char::is_ascii_digit(&c)).unwrap();
         let es = glsl_ver[..start].contains(" ES ");
         let ver = glsl_ver[start..]
@@ -45,6 +60,10 @@ impl ShaderVersion {
             } else {
                 Self::Es100
             }
+        } else if core_profile && (maj, min) >= (4, 10) {
+            Self::Gl410Core
+        } else if core_profile && (maj, min) >= (3, 30) {
+            Self::Gl330Core
         } else if maj > 1 || (maj == 1 && min >= 40) {
             Self::Gl140
         } else {
@@ -56,6 +75,8 @@ impl ShaderVersion {
         match self {
             Self::Gl120 => "#version 120\n",
             Self::Gl140 => "#version 140\n",
+            Self::Gl330Core => "#version 330 core\n",
+            Self::Gl410Core => "#version 410 core\n",
             Self::Es100 => "#version 100\n",
             Self::Es300 => "#version 300 es\n",
         }
@@ -63,15 +84,31 @@ impl ShaderVersion {
 
     pub(crate) fn is_new_shader_interface(&self) -> &'static str {
         match self {
-            ShaderVersion::Es300 | ShaderVersion::Gl140 => "#define NEW_SHADER_INTERFACE\n",
+            ShaderVersion::Es300
+            | ShaderVersion::Gl140
+            | ShaderVersion::Gl330Core
+            | ShaderVersion::Gl410Core => "#define NEW_SHADER_INTERFACE\n",
             _ => "",
         }
     }
 }
 
+/// Is this a desktop OpenGL core profile context?
+///
+/// The profile mask only exists since OpenGL 3.2, and never on OpenGL ES or WebGL.
+fn is_core_profile(gl: &glow::Context) -> bool {
+    use glow::HasContext as _;
+    let version = gl.version();
+    if version.is_embedded || (version.major, version.minor) < (3, 2) {
+        return false;
+    }
+    let profile_mask = unsafe { gl.get_parameter_i32(glow::CONTEXT_PROFILE_MASK) };
+    profile_mask & glow::CONTEXT_CORE_PROFILE_BIT as i32 != 0
+}
+
 #[test]
 fn test_shader_version() {
-    use ShaderVersion::{Es100, Es300, Gl120, Gl140};
+    use ShaderVersion::{Es100, Es300, Gl120, Gl140, Gl330Core, Gl410Core};
     for (s, v) in [
         ("1.2 OpenGL foo bar", Gl120),
         ("3.0", Gl140),
@@ -83,6 +120,17 @@ fn test_shader_version() {
         ("WebGL GLSL ES 3.00", Es300),
         ("WebGL GLSL ES 1.0 foo bar", Es100),
     ] {
-        assert_eq!(ShaderVersion::parse(s), v);
+        assert_eq!(ShaderVersion::parse(s, false), v);
+    }
+
+    for (s, v) in [
+        ("1.40", Gl140),
+        ("3.30", Gl330Core),
+        ("4.00", Gl330Core),
+        ("4.10", Gl410Core),
+        ("4.60 NVIDIA", Gl410Core),
+        ("OpenGL ES GLSL ES 3.20", Es300),
+    ] {
+        assert_eq!(ShaderVersion::parse(s, true), v);
     }
 }
@@ -0,0 +1,85 @@
+#![allow(unsafe_code)]
+
+use glow::HasContext as _;
+
+/// A buffer that we stream vertex or index data into every frame.
+///
+/// The data store only ever grows, to the largest upload seen so far,
+/// so that steady-state frames never need to allocate a bigger one.
+pub(crate) struct StreamBuffer {
+    buffer: glow::Buffer,
+    target: u32,
+    capacity: BufferCapacity,
+}
+
+impl StreamBuffer {
+    /// `target` is e.g. `glow::ARRAY_BUFFER` or `glow::ELEMENT_ARRAY_BUFFER`.
+    pub(crate) unsafe fn new(gl: &glow::Context, target: u32) -> Result<Self, String> {
+        Ok(Self {
+            buffer: gl.create_buffer()?,
+            target,
+            capacity: BufferCapacity::default(),
+        })
+    }
+
+    pub(crate) fn buffer(&self) -> glow::Buffer {
+        self.buffer
+    }
+
+    /// Bind the buffer and upload `data` to the start of it.
+    ///
+    /// `usage` is e.g. `glow::STREAM_DRAW`.
+    pub(crate) unsafe fn upload(&mut self, gl: &glow::Context, data: &[u8], usage: u32) {
+        gl.bind_buffer(self.target, Some(self.buffer));
+
+        // We always (re)specify the data store before writing to it, which also orphans the old one,
+        // so that the driver can hand us a fresh one instead of stalling until earlier draw calls are done reading from it.
+        // Since the size stays the same between growths, the driver can recycle the orphaned stores.
+        let capacity = self
+            .capacity
+            .grow_to_fit(data.len())
+            .unwrap_or(self.capacity.bytes);
+        gl.buffer_data_size(self.target, capacity as i32, usage);
+        gl.buffer_sub_data_u8_slice(self.target, 0, data);
+    }
+
+    pub(crate) unsafe fn destroy(&self, gl: &glow::Context) {
+        gl.delete_buffer(self.buffer);
+    }
+}
+
+/// The high-water mark of a [`StreamBuffer`].
+#[derive(Default)]
+struct BufferCapacity {
+    bytes: usize,
+}
+
+impl BufferCapacity {
+    /// Returns the new capacity if we need to grow to fit `len` bytes.
+    fn grow_to_fit(&mut self, len: usize) -> Option<usize> {
+        if self.bytes < len {
+            self.bytes = len.next_power_of_two();
+            Some(self.bytes)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_buffer_capacity() {
+    let frame = [64, 20 * 1000, 12, 20 * 4000, 0, 20 * 1500];
+
+    let mut capacity = BufferCapacity::default();
+    let growths = frame
+        .iter()
+        .filter_map(|&len| capacity.grow_to_fit(len))
+        .count();
+    assert_eq!(growths, 3);
+    assert!(capacity.bytes >= 20 * 4000);
+
+    // The second identical frame fits in what we already have:
+    for &len in &frame {
+        assert_eq!(capacity.grow_to_fit(len), None);
+    }
+}
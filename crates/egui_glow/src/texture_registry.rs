@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use egui::mutex::Mutex;
+
+/// Keeps track of the textures that [`crate::Painter`]s on the same context share,
+/// so that no painter deletes a texture that another one still paints with.
+///
+/// Give each painter the same registry with [`crate::Painter::set_texture_registry`],
+/// and then share textures with [`crate::Painter::register_shared_texture`].
+#[derive(Default)]
+pub struct TextureRegistry {
+    /// How many painters use each shared texture.
+    users: Mutex<HashMap<glow::Texture, usize>>,
+}
+
+impl TextureRegistry {
+    /// One more painter uses `texture`, in addition to the one that created it.
+    pub(crate) fn share(&self, texture: glow::Texture) {
+        *self.users.lock().entry(texture).or_insert(1) += 1;
+    }
+
+    /// A painter is done with `texture`.
+    ///
+    /// Returns `true` if no other painter uses it, i.e. if it should be deleted now.
+    pub(crate) fn release(&self, texture: glow::Texture) -> bool {
+        let mut users = self.users.lock();
+        match users.get_mut(&texture) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                users.remove(&texture);
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+#[allow(unsafe_code)]
+fn test_texture_registry() {
+    let texture = unsafe { glow::Context::create_texture_from_gl_name(1) };
+    let registry = TextureRegistry::default();
+    assert!(registry.release(texture), "not shared");
+
+    registry.share(texture);
+    registry.share(texture);
+    assert!(!registry.release(texture));
+    assert!(!registry.release(texture));
+    assert!(registry.release(texture), "the last user deletes it");
+}
@@ -1,4 +1,40 @@
+//! Vertex array objects, emulated where they are unavailable (WebGL1 and OpenGL ES 2 without `OES_vertex_array_object`).
+//!
+//! The [`crate::Painter`] uses these for its own meshes, but you can use them for geometry of your own too,
+//! e.g. in a [`crate::CallbackFn`]:
+//!
+//! ```no_run
+//! # let gl: &glow::Context = todo!();
+//! # let program: glow::Program = todo!();
+//! # let buffer: glow::Buffer = todo!();
+//! use egui_glow::glow::HasContext as _;
+//! use egui_glow::vao::{BufferInfo, VertexArrayObject};
+//!
+//! unsafe {
+//!     let location = gl.get_attrib_location(program, "a_pos").unwrap();
+//!     let position = BufferInfo {
+//!         location,
+//!         vector_size: 2,
+//!         data_type: glow::FLOAT,
+//!         normalized: false,
+//!         stride: 0,
+//!         offset: 0,
+//!     };
+//!     let native = egui_glow::vao::supports_vao(gl);
+//!     let vao = VertexArrayObject::new(gl, buffer, vec![position], native);
+//!
+//!     vao.bind(gl);
+//!     gl.draw_arrays(glow::TRIANGLES, 0, 3);
+//!     vao.unbind(gl);
+//!
+//!     vao.destroy(gl);
+//! }
+//! ```
+//!
+//! None of this is covered by semver guarantees beyond those of the rest of the crate.
+
 #![allow(unsafe_code)]
+#![allow(clippy::missing_safety_doc)] // The functions are unsafe because they make GL calls, like those of glow.
 
 use glow::HasContext as _;
 
@@ -6,100 +42,120 @@ use crate::check_for_gl_error;
 
 // ----------------------------------------------------------------------------
 
-#[derive(Debug)]
-pub(crate) struct BufferInfo {
-    pub location: u32, //
+/// One vertex attribute, as passed to `glVertexAttribPointer`.
+#[derive(Clone, Debug)]
+pub struct BufferInfo {
+    /// The location of the attribute in your program, e.g. from `gl.get_attrib_location(program, "a_pos")`.
+    ///
+    /// Every program you draw with must have the attribute at this location,
+    /// e.g. by calling `gl.bind_attrib_location` before linking.
+    pub location: u32,
+
+    /// The number of components, 1 to 4.
     pub vector_size: i32,
-    pub data_type: u32, //GL_FLOAT,GL_UNSIGNED_BYTE
+
+    /// The type of each component in the buffer, e.g. `glow::FLOAT` or `glow::UNSIGNED_BYTE`.
+    ///
+    /// The shader always gets floats (this uses `glVertexAttribPointer`, not `glVertexAttribIPointer`).
+    pub data_type: u32,
+
+    /// Map integer components to `0.0..=1.0` (or `-1.0..=1.0` for signed types) instead of converting them as-is.
     pub normalized: bool,
+
+    /// The distance in bytes between consecutive vertices, or 0 if they are tightly packed.
     pub stride: i32,
+
+    /// The offset in bytes of the first component in the buffer.
     pub offset: i32,
 }
 
 // ----------------------------------------------------------------------------
 
 /// Wrapper around either Emulated VAO or GL's VAO.
-pub(crate) struct VertexArrayObject {
+///
+/// It doesn't own the buffers, and doesn't record an element array buffer when emulated,
+/// so bind that yourself after [`Self::bind`].
+/// Call [`Self::destroy`] before dropping it.
+pub struct VertexArrayObject {
     // If `None`, we emulate VAO:s.
     vao: Option<crate::glow::VertexArray>,
-    vbo: glow::Buffer,
-    buffer_infos: Vec<BufferInfo>,
+
+    /// Each attribute, and the buffer it is read from.
+    attributes: Vec<(glow::Buffer, BufferInfo)>,
 }
 
 impl VertexArrayObject {
+    /// All attributes are read from the same (interleaved) `vbo`.
+    ///
+    /// Uses a real VAO if `native` (see [`supports_vao`]), and otherwise sets up the attributes on every bind.
     #[allow(clippy::needless_pass_by_value)] // false positive
-    pub(crate) unsafe fn new(
+    pub unsafe fn new(
         gl: &glow::Context,
         vbo: glow::Buffer,
         buffer_infos: Vec<BufferInfo>,
+        native: bool,
     ) -> Self {
-        let vao = if supports_vao(gl) {
-            let vao = gl.create_vertex_array().unwrap();
-            check_for_gl_error!(gl, "create_vertex_array");
+        let attributes = buffer_infos.into_iter().map(|info| (vbo, info)).collect();
+        Self::new_with_buffers(gl, attributes, native)
+    }
 
-            // Store state in the VAO:
-            gl.bind_vertex_array(Some(vao));
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-
-            for attribute in &buffer_infos {
-                gl.vertex_attrib_pointer_f32(
-                    attribute.location,
-                    attribute.vector_size,
-                    attribute.data_type,
-                    attribute.normalized,
-                    attribute.stride,
-                    attribute.offset,
-                );
-                check_for_gl_error!(gl, "vertex_attrib_pointer_f32");
-                gl.enable_vertex_attrib_array(attribute.location);
-                check_for_gl_error!(gl, "enable_vertex_attrib_array");
+    /// Each attribute is read from its own buffer, e.g. positions from one and colors from another.
+    pub unsafe fn new_with_buffers(
+        gl: &glow::Context,
+        attributes: Vec<(glow::Buffer, BufferInfo)>,
+        native: bool,
+    ) -> Self {
+        let vao = if native {
+            match gl.create_vertex_array() {
+                Ok(vao) => {
+                    // Store state in the VAO:
+                    gl.bind_vertex_array(Some(vao));
+                    set_attributes(gl, &attributes);
+                    gl.bind_vertex_array(None);
+
+                    Some(vao)
+                }
+                Err(err) => {
+                    crate::log::warn!("Emulating VAOs, because creating one failed: {}", err);
+                    None
+                }
             }
-
-            gl.bind_vertex_array(None);
-
-            Some(vao)
         } else {
-            tracing::debug!("VAO not supported");
             None
         };
 
-        Self {
-            vao,
-            vbo,
-            buffer_infos,
-        }
+        Self { vao, attributes }
     }
 
-    pub(crate) unsafe fn bind(&self, gl: &glow::Context) {
+    /// Are we using a real VAO, rather than emulating one?
+    pub fn is_native(&self) -> bool {
+        self.vao.is_some()
+    }
+
+    /// Bind the VAO, or set up its attributes when emulated, which changes the `ARRAY_BUFFER` binding.
+    pub unsafe fn bind(&self, gl: &glow::Context) {
         if let Some(vao) = self.vao {
             gl.bind_vertex_array(Some(vao));
             check_for_gl_error!(gl, "bind_vertex_array");
         } else {
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
-            check_for_gl_error!(gl, "bind_buffer");
+            set_attributes(gl, &self.attributes);
+        }
+    }
 
-            for attribute in &self.buffer_infos {
-                gl.vertex_attrib_pointer_f32(
-                    attribute.location,
-                    attribute.vector_size,
-                    attribute.data_type,
-                    attribute.normalized,
-                    attribute.stride,
-                    attribute.offset,
-                );
-                check_for_gl_error!(gl, "vertex_attrib_pointer_f32");
-                gl.enable_vertex_attrib_array(attribute.location);
-                check_for_gl_error!(gl, "enable_vertex_attrib_array");
-            }
+    /// Delete the VAO, but not its buffers.
+    pub unsafe fn destroy(&self, gl: &glow::Context) {
+        if let Some(vao) = self.vao {
+            gl.delete_vertex_array(vao);
         }
     }
 
-    pub(crate) unsafe fn unbind(&self, gl: &glow::Context) {
+    /// Unbind the VAO, or disable its attributes when emulated.
+    pub unsafe fn unbind(&self, gl: &glow::Context) {
         if self.vao.is_some() {
             gl.bind_vertex_array(None);
         } else {
             gl.bind_buffer(glow::ARRAY_BUFFER, None);
-            for attribute in &self.buffer_infos {
+            for (_, attribute) in &self.attributes {
                 gl.disable_vertex_attrib_array(attribute.location);
             }
         }
@@ -108,12 +164,41 @@ impl VertexArrayObject {
 
 // ----------------------------------------------------------------------------
 
-fn supports_vao(gl: &glow::Context) -> bool {
+/// Point each attribute at its buffer, leaving the last of the buffers bound.
+unsafe fn set_attributes(gl: &glow::Context, attributes: &[(glow::Buffer, BufferInfo)]) {
+    let mut bound_buffer = None;
+    for (buffer, attribute) in attributes {
+        if bound_buffer != Some(*buffer) {
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(*buffer));
+            check_for_gl_error!(gl, "bind_buffer");
+            bound_buffer = Some(*buffer);
+        }
+
+        gl.vertex_attrib_pointer_f32(
+            attribute.location,
+            attribute.vector_size,
+            attribute.data_type,
+            attribute.normalized,
+            attribute.stride,
+            attribute.offset,
+        );
+        check_for_gl_error!(gl, "vertex_attrib_pointer_f32");
+        gl.enable_vertex_attrib_array(attribute.location);
+        check_for_gl_error!(gl, "enable_vertex_attrib_array");
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// Whether VAOs are available, so [`VertexArrayObject`]s needn't be emulated.
+///
+/// This queries the GL version and extensions, so only call it once per context.
+pub fn supports_vao(gl: &glow::Context) -> bool {
     const WEBGL_PREFIX: &str = "WebGL ";
     const OPENGL_ES_PREFIX: &str = "OpenGL ES ";
 
     let version_string = unsafe { gl.get_parameter_string(glow::VERSION) };
-    tracing::debug!("GL version: {:?}.", version_string);
+    crate::log::debug!("GL version: {:?}.", version_string);
 
     // Examples:
     // * "WebGL 2.0 (OpenGL ES 3.0 Chromium)"
@@ -124,7 +209,7 @@ fn supports_vao(gl: &glow::Context) -> bool {
         if version_str.contains("1.0") {
             // need to test OES_vertex_array_object .
             let supported_extensions = gl.supported_extensions();
-            tracing::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
+            crate::log::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
             supported_extensions.contains("OES_vertex_array_object")
         } else {
             true
@@ -134,7 +219,7 @@ fn supports_vao(gl: &glow::Context) -> bool {
         if version_string.contains("2.0") {
             // need to test OES_vertex_array_object .
             let supported_extensions = gl.supported_extensions();
-            tracing::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
+            crate::log::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
             supported_extensions.contains("OES_vertex_array_object")
         } else {
             true
@@ -145,7 +230,7 @@ fn supports_vao(gl: &glow::Context) -> bool {
             // I found APPLE_vertex_array_object , GL_ATI_vertex_array_object ,ARB_vertex_array_object
             // but APPLE's and ATI's very old extension.
             let supported_extensions = gl.supported_extensions();
-            tracing::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
+            crate::log::debug!("Supported OpenGL extensions: {:?}", supported_extensions);
             supported_extensions.contains("ARB_vertex_array_object")
         } else {
             true
@@ -18,7 +18,7 @@ impl EguiGlow {
     ) -> Self {
         let painter = crate::Painter::new(gl, None, "")
             .map_err(|error| {
-                tracing::error!("error occurred in initializing painter:\n{}", error);
+                crate::log::error!("error occurred in initializing painter:\n{}", error);
             })
             .unwrap();
 
@@ -85,6 +85,7 @@ impl EguiGlow {
         for id in textures_delta.free.drain(..) {
             self.painter.free_texture(id);
         }
+        self.painter.flush_textures_to_destroy();
     }
 
     /// Call to release the allocated graphics resources.
@@ -0,0 +1,261 @@
+//! Tests that paint with [`egui_glow::HeadlessPainter`] and read back the result.
+//!
+//! They need an OpenGL context without a window, which we get from EGL (e.g. Mesa on Linux),
+//! so they are ignored by default. Run them with `cargo test -p egui_glow --features headless -- --ignored`,
+//! like CI does with Mesa's software renderer.
+
+#![cfg(all(feature = "headless", not(target_arch = "wasm32")))]
+
+use std::sync::Arc;
+
+use egui::{epaint::ImageDelta, Color32, ColorImage, TextureFilter};
+use glow::HasContext as _;
+use khronos_egl as egl;
+
+/// From `EGL_MESA_platform_surfaceless`.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// Keeps the EGL context of [`gl_context`] alive.
+struct EglContext {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+impl Drop for EglContext {
+    fn drop(&mut self) {
+        self.egl.make_current(self.display, None, None, None).ok();
+        self.egl.destroy_context(self.display, self.context).ok();
+        self.egl.terminate(self.display).ok();
+    }
+}
+
+/// A current OpenGL context without a surface, or `None` if EGL can't make one here.
+fn gl_context() -> Option<(Arc<glow::Context>, EglContext)> {
+    let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }.ok()?;
+    // Mesa's surfaceless platform needs no display server:
+    let display = match egl.upcast::<egl::EGL1_5>() {
+        Some(egl) => egl
+            .get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+            .ok(),
+        None => None,
+    }
+    .or_else(|| egl.get_display(egl::DEFAULT_DISPLAY))?;
+    egl.initialize(display).ok()?;
+    egl.bind_api(egl::OPENGL_API).ok()?;
+    let config = egl
+        .choose_first_config(
+            display,
+            &[
+                egl::RENDERABLE_TYPE,
+                egl::OPENGL_BIT,
+                egl::SURFACE_TYPE,
+                egl::PBUFFER_BIT,
+                egl::NONE,
+            ],
+        )
+        .ok()??;
+    let context = egl
+        .create_context(display, config, None, &[egl::NONE])
+        .ok()?;
+    let egl_context = EglContext {
+        egl,
+        display,
+        context,
+    };
+    // Needs `EGL_KHR_surfaceless_context`:
+    egl_context
+        .egl
+        .make_current(display, None, None, Some(context))
+        .ok()?;
+    let gl = unsafe {
+        glow::Context::from_loader_function(|name| {
+            egl_context
+                .egl
+                .get_proc_address(name)
+                .map_or(std::ptr::null(), |f| f as *const _)
+        })
+    };
+    Some((Arc::new(gl), egl_context))
+}
+
+/// Paint `texture_id` stretched over the whole image, after applying `delta` to it, and return the red channels.
+fn paint_texture(
+    painter: &mut egui_glow::HeadlessPainter,
+    texture_id: egui::TextureId,
+    delta: ImageDelta,
+) -> Vec<u8> {
+    let [width, height] = [4.0, 1.0];
+    let mut mesh = egui::Mesh::with_texture(texture_id);
+    mesh.add_rect_with_uv(
+        egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(width, height)),
+        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    let primitives = [egui::ClippedPrimitive {
+        clip_rect: egui::Rect::EVERYTHING,
+        primitive: egui::epaint::Primitive::Mesh(mesh),
+    }];
+    let mut textures_delta = egui::TexturesDelta::default();
+    textures_delta.set.push((texture_id, delta));
+    let image = painter.paint(1.0, &primitives, &textures_delta).unwrap();
+    image.pixels.iter().map(|color| color.r()).collect()
+}
+
+/// The `[TEXTURE_MIN_FILTER, TEXTURE_MAG_FILTER]` of the texture of `texture_id`.
+fn texture_filters(
+    gl: &glow::Context,
+    painter: &mut egui_glow::HeadlessPainter,
+    texture_id: egui::TextureId,
+) -> [u32; 2] {
+    let texture = painter.painter().texture(texture_id).unwrap();
+    unsafe {
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        let filters = [glow::TEXTURE_MIN_FILTER, glow::TEXTURE_MAG_FILTER]
+            .map(|parameter| gl.get_tex_parameter_i32(glow::TEXTURE_2D, parameter) as u32);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        filters
+    }
+}
+
+#[test]
+#[ignore = "needs an OpenGL context from EGL"]
+fn test_texture_filter_change() {
+    let (gl, _egl_context) = gl_context().expect("no OpenGL context from EGL");
+    let mut painter = egui_glow::HeadlessPainter::new(gl.clone(), [4, 1]).unwrap();
+
+    // Black on the left and white on the right, stretched over four pixels:
+    let texture_id = egui::TextureId::User(0);
+    let image = ColorImage {
+        size: [2, 1],
+        pixels: vec![Color32::BLACK, Color32::WHITE],
+    };
+
+    let nearest = paint_texture(
+        &mut painter,
+        texture_id,
+        ImageDelta::full(image.clone(), TextureFilter::Nearest),
+    );
+    assert_eq!(nearest, [0, 0, 255, 255]);
+    assert_eq!(
+        texture_filters(&gl, &mut painter, texture_id),
+        [glow::NEAREST, glow::NEAREST]
+    );
+
+    // The same texels, but a new filter, in a partial update:
+    let linear = paint_texture(
+        &mut painter,
+        texture_id,
+        ImageDelta::partial([0, 0], image.clone(), TextureFilter::Linear),
+    );
+    assert_eq!(linear[0], 0);
+    assert!(
+        0 < linear[1] && linear[1] < linear[2] && linear[2] < 255,
+        "expected a gradient in the middle, got {:?}",
+        linear
+    );
+    assert_eq!(linear[3], 255);
+    assert_eq!(
+        texture_filters(&gl, &mut painter, texture_id),
+        [glow::LINEAR, glow::LINEAR]
+    );
+
+    let nearest = paint_texture(
+        &mut painter,
+        texture_id,
+        ImageDelta::full(image, TextureFilter::Nearest),
+    );
+    assert_eq!(nearest, [0, 0, 255, 255]);
+    assert_eq!(
+        texture_filters(&gl, &mut painter, texture_id),
+        [glow::NEAREST, glow::NEAREST]
+    );
+
+    painter.destroy();
+}
+
+#[test]
+#[ignore = "needs an OpenGL context from EGL"]
+fn test_shader_patch_without_sampler() {
+    let (gl, _egl_context) = gl_context().expect("no OpenGL context from EGL");
+
+    // Sample nothing, so `u_sampler` is optimized out:
+    let result = egui_glow::Painter::new_with_shader_patch(gl, None, "", |shader_type, source| {
+        if shader_type == glow::FRAGMENT_SHADER {
+            source.replace("texture2D(u_sampler, tc)", "vec4(1.0)")
+        } else {
+            source
+        }
+    });
+    match result {
+        Err(egui_glow::PainterError::ProgramLink(err)) => {
+            assert!(err.contains("u_sampler"), "{}", err)
+        }
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(_) => panic!("expected an error for the missing u_sampler"),
+    }
+}
+
+#[test]
+#[ignore = "needs an OpenGL context from EGL"]
+fn test_sent_texture_of_zero_size() {
+    let (gl, _egl_context) = gl_context().expect("no OpenGL context from EGL");
+    let mut painter = egui_glow::HeadlessPainter::new(gl, [4, 1]).unwrap();
+
+    let sender = painter.painter().texture_sender();
+    let empty = ColorImage::new([0, 0], Color32::WHITE);
+    assert!(sender.set_texture(
+        egui::TextureId::User(0),
+        ImageDelta::full(empty, TextureFilter::Linear)
+    ));
+
+    // Skipped instead of panicking:
+    painter.painter().set_sent_textures();
+    assert_eq!(painter.painter().texture(egui::TextureId::User(0)), None);
+
+    painter.destroy();
+}
+
+#[test]
+#[ignore = "needs an OpenGL context from EGL"]
+fn test_program_cache() {
+    let (gl, egl_context) = gl_context().expect("no OpenGL context from EGL");
+    let get_proc_address = |name: &str| -> *const std::ffi::c_void {
+        egl_context
+            .egl
+            .get_proc_address(name)
+            .map_or(std::ptr::null(), |f| f as *const _)
+    };
+    let new_painter = |cached: Option<&[u8]>| {
+        let mut stored = None;
+        let painter = egui_glow::Painter::new_with_program_cache(
+            gl.clone(),
+            None,
+            "",
+            egui_glow::ProgramCache {
+                get_proc_address: &get_proc_address,
+                cached,
+                store: &mut |bytes: Vec<u8>| stored = Some(bytes),
+            },
+        );
+        painter.unwrap().destroy();
+        stored
+    };
+
+    let stored = new_painter(None).expect("the compiled program is stored");
+    assert_eq!(
+        new_painter(Some(stored.as_slice())),
+        None,
+        "loaded from the cache"
+    );
+
+    // Compiled again if the cache doesn't fit:
+    let mut corrupted = stored;
+    corrupted.truncate(16);
+    assert!(new_painter(Some(corrupted.as_slice())).is_some());
+    assert!(new_painter(Some(&b"not a program"[..])).is_some());
+}
@@ -747,47 +747,93 @@ pub struct PaintCallbackInfo {
     pub screen_size_px: [u32; 2],
 }
 
+/// A rectangle in physical pixels, rounded the same way the backends round it.
 pub struct ViewportInPixels {
     /// Physical pixel offset for left side of the viewport.
-    pub left_px: f32,
+    pub left_px: i32,
 
     /// Physical pixel offset for top side of the viewport.
-    pub top_px: f32,
+    pub top_px: i32,
 
     /// Physical pixel offset for bottom side of the viewport.
     ///
     /// This is what `glViewport`, `glScissor` etc expects for the y axis.
-    pub from_bottom_px: f32,
+    pub from_bottom_px: i32,
 
     /// Viewport width in physical pixels.
-    pub width_px: f32,
+    pub width_px: i32,
 
-    /// Viewport width in physical pixels.
-    pub height_px: f32,
+    /// Viewport height in physical pixels.
+    pub height_px: i32,
 }
 
 impl PaintCallbackInfo {
-    fn points_to_pixels(&self, rect: &Rect) -> ViewportInPixels {
+    /// Round the corners (rather than the size), so that rectangles that share an edge in points
+    /// also share it in pixels.
+    fn points_to_pixels(&self, rect: &Rect, clamp_to_screen: bool) -> ViewportInPixels {
+        let [screen_width, screen_height] = self.screen_size_px.map(|side| side as i32);
+
+        let mut min_x = (self.pixels_per_point * rect.min.x).round() as i32;
+        let mut min_y = (self.pixels_per_point * rect.min.y).round() as i32;
+        let mut max_x = (self.pixels_per_point * rect.max.x).round() as i32;
+        let mut max_y = (self.pixels_per_point * rect.max.y).round() as i32;
+
+        if clamp_to_screen {
+            min_x = min_x.clamp(0, screen_width);
+            min_y = min_y.clamp(0, screen_height);
+            max_x = max_x.clamp(min_x, screen_width);
+            max_y = max_y.clamp(min_y, screen_height);
+        }
+
         ViewportInPixels {
-            left_px: rect.min.x * self.pixels_per_point,
-            top_px: rect.min.y * self.pixels_per_point,
-            from_bottom_px: self.screen_size_px[1] as f32 - rect.max.y * self.pixels_per_point,
-            width_px: rect.width() * self.pixels_per_point,
-            height_px: rect.height() * self.pixels_per_point,
+            left_px: min_x,
+            top_px: min_y,
+            from_bottom_px: screen_height - max_y,
+            width_px: max_x - min_x,
+            height_px: max_y - min_y,
         }
     }
 
     /// The viewport rectangle. This is what you would use in e.g. `glViewport`.
+    ///
+    /// These are the exact values the painter sets the viewport to before calling the callback.
     pub fn viewport_in_pixels(&self) -> ViewportInPixels {
-        self.points_to_pixels(&self.viewport)
+        self.points_to_pixels(&self.viewport, false)
     }
 
     /// The "scissor" or "clip" rectangle. This is what you would use in e.g. `glScissor`.
+    ///
+    /// Clamped to the screen, and with each edge rounded to the nearest pixel.
+    /// That is the scissor rectangle `egui_glow` sets with its default `ClipRounding::Round`,
+    /// as long as it isn't overridden by `Painter::push_pixel_clip` or limited to the damage of a frame.
     pub fn clip_rect_in_pixels(&self) -> ViewportInPixels {
-        self.points_to_pixels(&self.clip_rect)
+        self.points_to_pixels(&self.clip_rect, true)
     }
 }
 
+#[test]
+fn paint_callback_info_in_pixels() {
+    let info = PaintCallbackInfo {
+        viewport: Rect::from_min_max(Pos2::new(10.3, 20.0), Pos2::new(30.3, 40.0)),
+        clip_rect: Rect::from_min_max(Pos2::new(-5.0, 0.0), Pos2::new(1000.0, 30.0)),
+        pixels_per_point: 1.5,
+        screen_size_px: [300, 200],
+    };
+
+    // The corners are rounded, not the size:
+    let viewport = info.viewport_in_pixels();
+    assert_eq!(viewport.left_px, 15);
+    assert_eq!(viewport.width_px, 45 - 15);
+    assert_eq!(viewport.top_px, 30);
+    assert_eq!(viewport.from_bottom_px, 200 - 60);
+
+    let clip_rect = info.clip_rect_in_pixels();
+    assert_eq!(clip_rect.left_px, 0);
+    assert_eq!(clip_rect.width_px, 300);
+    assert_eq!(clip_rect.from_bottom_px, 200 - 45);
+    assert_eq!(clip_rect.height_px, 45);
+}
+
 /// If you want to paint some 3D shapes inside an egui region, you can use this.
 ///
 /// This is advanced usage, and is backend specific.